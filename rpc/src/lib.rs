@@ -0,0 +1,164 @@
+//! RPC interface for the stream payments pallet.
+
+use std::sync::Arc;
+
+use codec::Codec;
+use jsonrpsee::{
+    core::{Error as JsonRpseeError, RpcResult},
+    proc_macros::rpc,
+    types::error::{CallError, ErrorObject},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+
+pub use pallet_stream_payments_runtime_api::StreamPaymentsApi as StreamPaymentsRuntimeApi;
+use pallet_stream_payments_runtime_api::{Stream, StreamAsset, StreamId};
+
+/// Stream payments RPC methods.
+#[rpc(client, server)]
+pub trait StreamPaymentsApi<BlockHash, AccountId, AssetId, Balance, BlockNumber> {
+    /// All open streams of `source`.
+    #[method(name = "streamPayments_streamsBySource")]
+    fn streams_by_source(
+        &self,
+        source: AccountId,
+        at: Option<BlockHash>,
+    ) -> RpcResult<Vec<Stream<AccountId, AssetId, Balance, BlockNumber>>>;
+
+    /// All open streams paying `target`, either as their target or as a recipient of their
+    /// shares, along with their sources.
+    #[method(name = "streamPayments_streamsByTarget")]
+    fn streams_by_target(
+        &self,
+        target: AccountId,
+        at: Option<BlockHash>,
+    ) -> RpcResult<Vec<(AccountId, Stream<AccountId, AssetId, Balance, BlockNumber>)>>;
+
+    /// The amount of `asset` committed per block by the active streams of `account`.
+    #[method(name = "streamPayments_outflowPerBlock")]
+    fn outflow_per_block(
+        &self,
+        account: AccountId,
+        asset: StreamAsset<AssetId>,
+        at: Option<BlockHash>,
+    ) -> RpcResult<Balance>;
+
+    /// The number of blocks for which the free balance of `account` covers its streams of
+    /// `asset`, or `None` if they pay nothing.
+    #[method(name = "streamPayments_runway")]
+    fn runway(
+        &self,
+        account: AccountId,
+        asset: StreamAsset<AssetId>,
+        at: Option<BlockHash>,
+    ) -> RpcResult<Option<BlockNumber>>;
+
+    /// The amount every open stream would pay if it was settled `blocks` blocks from now.
+    #[method(name = "streamPayments_projectedPayments")]
+    fn projected_payments(
+        &self,
+        blocks: BlockNumber,
+        at: Option<BlockHash>,
+    ) -> RpcResult<Vec<(StreamId, Balance)>>;
+}
+
+/// Provides RPC methods to query streams.
+pub struct StreamPayments<C, Block> {
+    client: Arc<C>,
+    _marker: std::marker::PhantomData<Block>,
+}
+
+impl<C, Block> StreamPayments<C, Block> {
+    /// Create new `StreamPayments` with the given reference to the client.
+    pub fn new(client: Arc<C>) -> Self {
+        Self {
+            client,
+            _marker: Default::default(),
+        }
+    }
+}
+
+/// Error code of failed runtime API calls.
+const RUNTIME_ERROR: i32 = 1;
+
+/// Convert a runtime API error into an RPC error.
+fn runtime_error_into_rpc_err(err: impl std::fmt::Debug) -> JsonRpseeError {
+    CallError::Custom(ErrorObject::owned(
+        RUNTIME_ERROR,
+        "Runtime error",
+        Some(format!("{:?}", err)),
+    ))
+    .into()
+}
+
+impl<C, Block, AccountId, AssetId, Balance, BlockNumber>
+    StreamPaymentsApiServer<<Block as BlockT>::Hash, AccountId, AssetId, Balance, BlockNumber>
+    for StreamPayments<C, Block>
+where
+    Block: BlockT,
+    C: ProvideRuntimeApi<Block> + HeaderBackend<Block> + Send + Sync + 'static,
+    C::Api: StreamPaymentsRuntimeApi<Block, AccountId, AssetId, Balance, BlockNumber>,
+    AccountId: Codec + Serialize + DeserializeOwned + Send + Sync + 'static,
+    AssetId: Codec + Serialize + DeserializeOwned + Send + Sync + 'static,
+    Balance: Codec + Serialize + Send + Sync + 'static,
+    BlockNumber: Codec + Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    fn streams_by_source(
+        &self,
+        source: AccountId,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> RpcResult<Vec<Stream<AccountId, AssetId, Balance, BlockNumber>>> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        api.streams_by_source(&at, source)
+            .map_err(runtime_error_into_rpc_err)
+    }
+
+    fn streams_by_target(
+        &self,
+        target: AccountId,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> RpcResult<Vec<(AccountId, Stream<AccountId, AssetId, Balance, BlockNumber>)>> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        api.streams_by_target(&at, target)
+            .map_err(runtime_error_into_rpc_err)
+    }
+
+    fn outflow_per_block(
+        &self,
+        account: AccountId,
+        asset: StreamAsset<AssetId>,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> RpcResult<Balance> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        api.outflow_per_block(&at, account, asset)
+            .map_err(runtime_error_into_rpc_err)
+    }
+
+    fn runway(
+        &self,
+        account: AccountId,
+        asset: StreamAsset<AssetId>,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> RpcResult<Option<BlockNumber>> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        api.runway(&at, account, asset)
+            .map_err(runtime_error_into_rpc_err)
+    }
+
+    fn projected_payments(
+        &self,
+        blocks: BlockNumber,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> RpcResult<Vec<(StreamId, Balance)>> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        api.projected_payments(&at, blocks)
+            .map_err(runtime_error_into_rpc_err)
+    }
+}
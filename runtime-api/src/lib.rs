@@ -0,0 +1,38 @@
+//! Runtime API definition for the stream payments pallet.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+// The generated API code triggers these lints
+#![allow(clippy::too_many_arguments, clippy::unnecessary_mut_passed)]
+
+use codec::Codec;
+use sp_std::prelude::*;
+
+pub use pallet_stream_payments::{Stream, StreamAsset, StreamId};
+
+sp_api::decl_runtime_apis! {
+    /// Queries of open streams, their outflows and projected payments.
+    pub trait StreamPaymentsApi<AccountId, AssetId, Balance, BlockNumber>
+    where
+        AccountId: Codec,
+        AssetId: Codec,
+        Balance: Codec,
+        BlockNumber: Codec,
+    {
+        /// All open streams of `source`.
+        fn streams_by_source(source: AccountId) -> Vec<Stream<AccountId, AssetId, Balance, BlockNumber>>;
+
+        /// All open streams paying `target`, either as their target or as a recipient of their
+        /// shares, along with their sources.
+        fn streams_by_target(target: AccountId) -> Vec<(AccountId, Stream<AccountId, AssetId, Balance, BlockNumber>)>;
+
+        /// The amount of `asset` committed per block by the active streams of `account`.
+        fn outflow_per_block(account: AccountId, asset: StreamAsset<AssetId>) -> Balance;
+
+        /// The number of blocks for which the free balance of `account` covers its streams of
+        /// `asset`, or `None` if they pay nothing.
+        fn runway(account: AccountId, asset: StreamAsset<AssetId>) -> Option<BlockNumber>;
+
+        /// The amount every open stream would pay if it was settled `blocks` blocks from now.
+        fn projected_payments(blocks: BlockNumber) -> Vec<(StreamId, Balance)>;
+    }
+}
@@ -6,7 +6,8 @@ use super::*;
 
 use crate::Pallet as StreamPayments;
 use frame_benchmarking::{account, benchmarks, whitelisted_caller};
-use frame_support::traits::{Get, OnInitialize};
+use frame_support::sp_runtime::Perbill;
+use frame_support::traits::Get;
 use frame_system::RawOrigin;
 
 const SEED: u32 = 609;
@@ -23,43 +24,360 @@ fn open_n_streams<T: Config, V: Into<BalanceOf<T>>>(
         StreamPayments::<T>::open_stream(
             RawOrigin::Signed(caller.clone()).into(),
             target,
+            StreamAsset::Native,
             spend_rate,
+            Default::default(),
         )?;
     }
     Ok(())
 }
 
+/// Open a stream from the caller to the first target, split between the target and `r` other
+/// recipients.
+fn open_split_stream<T: Config>(r: u32) -> Result<StreamId, &'static str> {
+    let caller: T::AccountId = whitelisted_caller();
+    T::Currency::make_free_balance_be(&caller, 1_000_000_000u32.into());
+    let target: T::AccountId = account("target", 0, SEED);
+    let share = Perbill::from_rational(1, T::MaxRecipients::get() + 1);
+    let shares: Vec<_> = (0..r)
+        .map(|i| (account("recipient", i, SEED), share))
+        .collect();
+    let id = StreamPayments::<T>::next_stream_id();
+    StreamPayments::<T>::open_split_stream(
+        RawOrigin::Signed(caller).into(),
+        target,
+        StreamAsset::Native,
+        1000u32.into(),
+        shares,
+        Default::default(),
+    )?;
+    Ok(id)
+}
+
+/// Open `n` streams from the caller, each paying 1% of the free balance of the caller until
+/// `floor` is reached.
+fn open_n_share_streams<T: Config>(n: u32, floor: BalanceOf<T>) -> Result<(), &'static str> {
+    let caller: T::AccountId = whitelisted_caller();
+    T::Currency::make_free_balance_be(&caller, 1_000_000_000u32.into());
+    for i in 0..n {
+        let target: T::AccountId = account("target", i, SEED);
+        StreamPayments::<T>::open_share_stream(
+            RawOrigin::Signed(caller.clone()).into(),
+            target,
+            StreamAsset::Native,
+            Perbill::from_percent(1),
+            floor,
+            Default::default(),
+        )?;
+    }
+    Ok(())
+}
+
+fn next_block<T: Config>() {
+    let now = frame_system::Pallet::<T>::block_number();
+    frame_system::Pallet::<T>::set_block_number(now + 1u32.into());
+}
+
 benchmarks! {
     open_stream {
         let caller: T::AccountId = whitelisted_caller();
         let target: T::AccountId = account("target", 1, SEED);
         T::Currency::make_free_balance_be(&caller, 1_000_000_000u32.into());
+        // Worst case: the target only accepts streams from allowed sources
+        IncomingPolicies::<T>::insert(&target, IncomingPolicy::AllowList);
+        AllowedSources::<T>::insert(&target, &caller, ());
+        let asset = StreamAsset::Native;
+        let spend_rate: BalanceOf<T> = 10u32.into();
+    }: _(RawOrigin::Signed(caller.clone()), target, asset, spend_rate, Default::default())
+    verify {
+        assert_eq!(StreamPayments::<T>::streams(caller).len(), 1u32 as usize);
+    }
+
+    // Funding the escrow adds a transfer to a new account.
+    open_escrowed_stream {
+        let caller: T::AccountId = whitelisted_caller();
+        let target: T::AccountId = account("target", 1, SEED);
+        T::Currency::make_free_balance_be(&caller, 1_000_000_000u32.into());
+        IncomingPolicies::<T>::insert(&target, IncomingPolicy::AllowList);
+        AllowedSources::<T>::insert(&target, &caller, ());
+        let asset = StreamAsset::Native;
+        let spend_rate: BalanceOf<T> = 10u32.into();
+        let options = StreamOptions {
+            escrow: Some(1000u32.into()),
+            ..Default::default()
+        };
+    }: open_stream(RawOrigin::Signed(caller.clone()), target, asset, spend_rate, options)
+    verify {
+        assert!(StreamPayments::<T>::streams(caller)[0].escrowed);
+    }
+
+    open_split_stream {
+        let r in 0..T::MaxRecipients::get();
+        let caller: T::AccountId = whitelisted_caller();
+        let target: T::AccountId = account("target", 1, SEED);
+        T::Currency::make_free_balance_be(&caller, 1_000_000_000u32.into());
+        let asset = StreamAsset::Native;
         let spend_rate: BalanceOf<T> = 10u32.into();
-    }: _(RawOrigin::Signed(caller.clone()), target, spend_rate)
+        let share = Perbill::from_rational(1, T::MaxRecipients::get().max(1));
+        let shares: Vec<_> = (0..r)
+            .map(|i| (account("recipient", i, SEED), share))
+            .collect();
+    }: _(RawOrigin::Signed(caller.clone()), target, asset, spend_rate, shares, Default::default())
     verify {
         assert_eq!(StreamPayments::<T>::streams(caller).len(), 1u32 as usize);
     }
 
     // Fill in the streams vector for a single account up to the max and then remove
     // a single stream from every position. It's imperfect because it relies on MaxStreams,
-    // but still better than doing just one case.
+    // but still better than doing just one case. The last stream pays `r` recipients besides
+    // its target.
     close_stream {
         let i in 0..(T::MaxStreams::get() - 1);  // Range end seems to be **inclusive** (ugh!)
-        open_n_streams::<T, u32>(T::MaxStreams::get(), 1000)?;
+        let r in 0..T::MaxRecipients::get();
+        open_n_streams::<T, u32>(T::MaxStreams::get() - 1, 1000)?;
+        let last = open_split_stream::<T>(r)?;
         let caller: T::AccountId = whitelisted_caller();
-    }: _(RawOrigin::Signed(caller.clone()), i)
+        let id = if i == T::MaxStreams::get() - 1 { last } else { i as StreamId };
+        // Closing settles the stream, so make a payment due
+        next_block::<T>();
+    }: _(RawOrigin::Signed(caller.clone()), id)
     verify {
         assert_eq!(StreamPayments::<T>::streams(caller).len(), (T::MaxStreams::get() - 1) as usize);
     }
 
+    // Settling a stream is independent of the number of open streams, so unlike the former
+    // per-block processing it is benchmarked with a single stream of the caller, paying `r`
+    // recipients besides the target.
+    settle_stream {
+        let r in 0..T::MaxRecipients::get();
+        let id = open_split_stream::<T>(r)?;
+        let caller: T::AccountId = whitelisted_caller();
+        next_block::<T>();
+    }: _(RawOrigin::Signed(caller.clone()), id)
+    verify {
+        assert_eq!(StreamPayments::<T>::streams(caller)[0].payments_made, 1);
+    }
+
+    // Share streams cannot be split, but every payment depends on the balance of the source.
+    // Worst case: the payment brings the source to its floor, so the stream is closed as well.
+    settle_share_stream {
+        let caller: T::AccountId = whitelisted_caller();
+        let free = BalanceOf::<T>::from(1_000_000_000u32) - T::StreamDeposit::get();
+        open_n_share_streams::<T>(1, free - free / BalanceOf::<T>::from(200u32))?;
+        let id = StreamPayments::<T>::streams(&caller)[0].id;
+        next_block::<T>();
+    }: settle_stream(RawOrigin::Signed(caller.clone()), id)
+    verify {
+        assert_eq!(StreamPayments::<T>::stream_source(id), None);
+    }
+
+    accept_stream {
+        let target: T::AccountId = account("target", 0, SEED);
+        IncomingPolicies::<T>::insert(&target, IncomingPolicy::RequireAcceptance);
+        open_n_streams::<T, u32>(1, 1000)?;
+        let caller: T::AccountId = whitelisted_caller();
+        let id = StreamPayments::<T>::streams(&caller)[0].id;
+    }: _(RawOrigin::Signed(target), id)
+    verify {
+        assert_eq!(StreamPayments::<T>::streams(caller)[0].state, StreamState::Active);
+    }
+
+    // Closing the stream removes the incoming stream entries of all recipients.
+    reject_stream {
+        let r in 0..T::MaxRecipients::get();
+        let id = open_split_stream::<T>(r)?;
+        let caller: T::AccountId = whitelisted_caller();
+        let target: T::AccountId = account("target", 0, SEED);
+    }: _(RawOrigin::Signed(target), id)
+    verify {
+        assert_eq!(StreamPayments::<T>::streams(caller).len(), 0);
+    }
+
+    set_incoming_policy {
+        let caller: T::AccountId = whitelisted_caller();
+    }: _(RawOrigin::Signed(caller.clone()), IncomingPolicy::AllowList)
+    verify {
+        assert_eq!(StreamPayments::<T>::incoming_policy(caller), IncomingPolicy::AllowList);
+    }
+
+    set_allowed_source {
+        let caller: T::AccountId = whitelisted_caller();
+        let source: T::AccountId = account("source", 0, SEED);
+    }: _(RawOrigin::Signed(caller.clone()), source.clone(), true)
+    verify {
+        assert_eq!(StreamPayments::<T>::allowed_source(caller, source), Some(()));
+    }
+
+    withdraw {
+        let r in 0..T::MaxRecipients::get();
+        let id = open_split_stream::<T>(r)?;
+        let caller: T::AccountId = whitelisted_caller();
+        let target: T::AccountId = account("target", 0, SEED);
+        next_block::<T>();
+    }: _(RawOrigin::Signed(target), id)
+    verify {
+        assert_eq!(StreamPayments::<T>::streams(caller)[0].payments_made, 1);
+    }
+
+    transfer_stream_target {
+        open_n_streams::<T, u32>(1, 1000)?;
+        let caller: T::AccountId = whitelisted_caller();
+        let target: T::AccountId = account("target", 0, SEED);
+        let new_target: T::AccountId = account("new_target", 0, SEED);
+        // Worst case: the new target only accepts streams from allowed sources
+        IncomingPolicies::<T>::insert(&new_target, IncomingPolicy::AllowList);
+        AllowedSources::<T>::insert(&new_target, &caller, ());
+        let id = StreamPayments::<T>::streams(&caller)[0].id;
+        // Transferring settles the stream, so make a payment due
+        next_block::<T>();
+    }: _(RawOrigin::Signed(target), id, new_target.clone())
+    verify {
+        assert_eq!(StreamPayments::<T>::streams(caller)[0].target, new_target);
+    }
+
+    transfer_stream_source {
+        open_n_streams::<T, u32>(1, 1000)?;
+        let caller: T::AccountId = whitelisted_caller();
+        let new_source: T::AccountId = account("new_source", 0, SEED);
+        let id = StreamPayments::<T>::streams(&caller)[0].id;
+    }: _(RawOrigin::Signed(caller), id, new_source.clone())
+    verify {
+        assert_eq!(StreamPayments::<T>::source_transfer(id), Some(new_source));
+    }
+
+    // Every recipient must accept the new source and is paid when the stream is settled.
+    accept_stream_source {
+        let r in 0..T::MaxRecipients::get();
+        let id = open_split_stream::<T>(r)?;
+        let caller: T::AccountId = whitelisted_caller();
+        let new_source: T::AccountId = account("new_source", 0, SEED);
+        T::Currency::make_free_balance_be(&new_source, 1_000_000_000u32.into());
+        StreamPayments::<T>::transfer_stream_source(
+            RawOrigin::Signed(caller.clone()).into(),
+            id,
+            new_source.clone(),
+        )?;
+        // Accepting settles the stream, so make a payment due
+        next_block::<T>();
+    }: _(RawOrigin::Signed(new_source.clone()), id, 1000u32.into(), 0u32.into())
+    verify {
+        assert_eq!(StreamPayments::<T>::stream_source(id), Some(new_source));
+    }
+
+    update_stream {
+        open_n_streams::<T, u32>(1, 1000)?;
+        let caller: T::AccountId = whitelisted_caller();
+        let id = StreamPayments::<T>::streams(&caller)[0].id;
+        let spend_rate: BalanceOf<T> = 2000u32.into();
+        // Updating settles the stream, so make a payment due
+        next_block::<T>();
+    }: _(RawOrigin::Signed(caller.clone()), id, spend_rate, None)
+    verify {
+        assert_eq!(StreamPayments::<T>::streams(caller)[0].spend_rate, spend_rate);
+    }
+
+    top_up_stream {
+        let caller: T::AccountId = whitelisted_caller();
+        let target: T::AccountId = account("target", 0, SEED);
+        T::Currency::make_free_balance_be(&caller, 1_000_000_000u32.into());
+        let options = StreamOptions {
+            escrow: Some(1_000_000u32.into()),
+            ..Default::default()
+        };
+        StreamPayments::<T>::open_stream(
+            RawOrigin::Signed(caller.clone()).into(),
+            target,
+            StreamAsset::Native,
+            1000u32.into(),
+            options,
+        )?;
+        let id = StreamPayments::<T>::streams(&caller)[0].id;
+        let amount: BalanceOf<T> = 1_000_000u32.into();
+    }: _(RawOrigin::Signed(caller.clone()), id, amount)
+    verify {
+        assert_eq!(
+            T::Currency::free_balance(&StreamPayments::<T>::escrow_account(id)),
+            2_000_000u32.into()
+        );
+    }
+
+    reduce_funding {
+        let caller: T::AccountId = whitelisted_caller();
+        let target: T::AccountId = account("target", 0, SEED);
+        T::Currency::make_free_balance_be(&caller, 1_000_000_000u32.into());
+        let options = StreamOptions {
+            escrow: Some(1_000_000u32.into()),
+            ..Default::default()
+        };
+        StreamPayments::<T>::open_stream(
+            RawOrigin::Signed(caller.clone()).into(),
+            target,
+            StreamAsset::Native,
+            1000u32.into(),
+            options,
+        )?;
+        let id = StreamPayments::<T>::streams(&caller)[0].id;
+        let amount: BalanceOf<T> = 500_000u32.into();
+        // Reducing the funding settles the stream, so make a payment due
+        next_block::<T>();
+    }: _(RawOrigin::Signed(caller.clone()), id, amount)
+    verify {
+        assert_eq!(
+            T::Currency::free_balance(&StreamPayments::<T>::escrow_account(id)),
+            499_000u32.into()
+        );
+    }
+
+    pause_stream {
+        open_n_streams::<T, u32>(1, 1000)?;
+        let caller: T::AccountId = whitelisted_caller();
+        let id = StreamPayments::<T>::streams(&caller)[0].id;
+        // Pausing settles the stream, so make a payment due
+        next_block::<T>();
+    }: _(RawOrigin::Signed(caller.clone()), id, None)
+    verify {
+        assert_eq!(
+            StreamPayments::<T>::streams(caller)[0].state,
+            StreamState::Paused { resume_at: None }
+        );
+    }
+
+    resume_stream {
+        open_n_streams::<T, u32>(1, 1000)?;
+        let caller: T::AccountId = whitelisted_caller();
+        let id = StreamPayments::<T>::streams(&caller)[0].id;
+        StreamPayments::<T>::pause_stream(RawOrigin::Signed(caller.clone()).into(), id, None)?;
+        next_block::<T>();
+    }: _(RawOrigin::Signed(caller.clone()), id)
+    verify {
+        assert_eq!(StreamPayments::<T>::streams(caller)[0].state, StreamState::Active);
+    }
+
+    claim {
+        let r in 0..T::MaxRecipients::get();
+        let id = open_split_stream::<T>(r)?;
+        let caller: T::AccountId = whitelisted_caller();
+        let target: T::AccountId = account("target", 0, SEED);
+        let beneficiary: T::AccountId = account("beneficiary", 0, SEED);
+        next_block::<T>();
+    }: _(RawOrigin::Signed(target), id, beneficiary)
+    verify {
+        assert_eq!(StreamPayments::<T>::streams(caller)[0].payments_made, 1);
+    }
+
+    // Settle `i` streams, the last of which pays `r` recipients besides its target.
     on_initialize_transfer {
-        let i in 0..(T::MaxStreams::get() - 1);  // Range end seems to be **inclusive** (ugh!)
-        open_n_streams::<T, u32>(i, 1000)?;
+        let i in 1..(T::MaxStreams::get() - 1);  // Range end seems to be **inclusive** (ugh!)
+        let r in 0..T::MaxRecipients::get();
+        open_n_streams::<T, u32>(i - 1, 1000)?;
+        open_split_stream::<T>(r)?;
         let caller: T::AccountId = whitelisted_caller();
+        next_block::<T>();
+        let now = frame_system::Pallet::<T>::block_number();
     } : {
-        StreamPayments::<T>::on_initialize(2u32.into());
+        StreamPayments::<T>::settle_streams(now, i);
     } verify {
-        assert_eq!(StreamPayments::<T>::streams(caller).len(), i as usize);
+        assert!(StreamPayments::<T>::streams(caller).iter().all(|stream| stream.payments_made == 1));
     }
 
     on_initialize_stream_exhausted {
@@ -70,11 +388,25 @@ benchmarks! {
         open_n_streams::<T, BalanceOf<T>>(i, spend_rate)?;
         let caller: T::AccountId = whitelisted_caller();
         T::Currency::make_free_balance_be(&caller, 0u32.into());
+        next_block::<T>();
+        let now = frame_system::Pallet::<T>::block_number();
     } : {
-        StreamPayments::<T>::on_initialize(2u32.into());
+        StreamPayments::<T>::settle_streams(now, i);
     } verify {
         assert_eq!(*StreamPayments::<T>::streams(caller), []);
     }
 
+    on_initialize_share_payment {
+        let i in 0..(T::MaxStreams::get() - 1);  // Range end seems to be **inclusive** (ugh!)
+        open_n_share_streams::<T>(i, 0u32.into())?;
+        let caller: T::AccountId = whitelisted_caller();
+        next_block::<T>();
+        let now = frame_system::Pallet::<T>::block_number();
+    } : {
+        StreamPayments::<T>::settle_streams(now, i);
+    } verify {
+        assert!(StreamPayments::<T>::streams(caller).iter().all(|stream| stream.payments_made == 1));
+    }
+
     impl_benchmark_test_suite!(StreamPayments, crate::mock::new_test_ext(), crate::mock::Test);
 }
@@ -3,32 +3,112 @@
 //! ## Overview
 //!
 //! This pallet supports creating *streams* i.e. ongoing payments. Once a stream is opened,
-//! on every block a specified amount of funds will be transferred from the origin account
-//! to the given target account, until the stream is closed.
+//! on every block a specified amount of funds becomes owed by the origin account to the given
+//! target account, until the stream is closed.
+//!
+//! Streams can pay either in the native currency or in any asset provided by `Assets` (e.g.
+//! `pallet-assets`). The stream deposit is always reserved in the native currency.
+//!
+//! Streams are settled lazily: no work is done for open streams on every block. Instead, the
+//! amount owed since the last settlement (i.e. `spend_rate` times the number of payments which
+//! have become due) is transferred whenever the stream is settled – when the target withdraws
+//! the funds, when anyone settles the stream explicitly or when the stream is closed.
+//!
+//! Additionally, up to `MaxStreamsPerBlock` streams are settled automatically in every block –
+//! either at the beginning of the block or, depending on `SettlementHook`, in `on_idle` using
+//! only the weight left after all extrinsics. Processing resumes where it stopped in the previous
+//! block, so every stream is eventually reached and pays its whole backlog at once.
+//!
+//! Streams of any kind (fixed-rate, split or share) can also be opened at genesis through the
+//! `streams` field of the genesis config, with the same validation as `open_stream`.
+//!
+//! The streams paying every account, either as their target or as a recipient of their shares,
+//! are indexed in `IncomingStreams`, bounded by `MaxIncomingStreams` so that accounts cannot be
+//! spammed with streams.
+//!
+//! The receiving side of every stream is exposed as a non-fungible item through the
+//! `nonfungibles::{Inspect, Transfer}` traits, so that it can be displayed and traded by generic
+//! NFT tooling. See the `nonfungibles` module for details.
 //!
 //! ## Interface
 //!
 //! ### Config
 //!
+//! * `AssetId` – The identifier of assets which can be streamed besides the native currency.
+//! * `Assets` – The fungible assets which can be streamed besides the native currency.
+//! * `PalletId: PalletId` – The identifier from which the escrow accounts of streams are derived.
 //! * `MaxStreams: u32` – The maximum number of streams per account.
+//! * `MaxRecipients: u32` – The maximum number of recipients of a split stream besides its target.
+//! * `MaxIncomingStreams: u32` – The maximum number of streams paying a single account.
+//! * `MaxStreamsPerBlock: u32` – The maximum number of streams settled automatically per block.
+//! * `SettlementHook: SettlementHook` – The hook in which streams are settled automatically.
+//! * `ProposalLifetime: BlockNumber` – The number of blocks in which a stream proposed to a
+//!   target requiring acceptance must be accepted.
 //!
 //! ### Dispatchable functions
 //!
-//! * `open_stream(origin, target, spend_rate)` – Open a new stream. From the next block on,
-//!   on each block `spend_rate` will be transferred to the `target` account. The stream can be
-//!   closed by calling `close_stream`.
-//! * `close_stream(origin, index)` – Close a stream. From the next block on, transfers will stop.
+//! * `open_stream(origin, target, asset, spend_rate, options)` – Open a new stream. From the
+//!   next block on, on each block `spend_rate` of `asset` will be owed to the `target` account.
+//!   The stream can be closed by calling `close_stream`. `options` allow to cap the total amount
+//!   paid by the stream (`max_amount`, reserved up front – native currency only), close it after
+//!   a given block (`ends_at`), make no payments before a given block (`cliff`), pay once every
+//!   `period` blocks, tolerate up to `grace` consecutive missed payments and pay from a
+//!   dedicated escrow account funded up front (`escrow`). Streams of assets other than the
+//!   native currency cannot be capped, as `Assets` provides no reserves – their total payments
+//!   can be bounded with an escrow instead.
+//! * `open_split_stream(origin, target, asset, spend_rate, shares, options)` – Open a new stream
+//!   whose payments are split between the `target` and other recipients according to `shares`.
+//!   The target receives the rest of every payment, including the rounding dust.
+//! * `open_share_stream(origin, target, asset, share, floor, options)` – Open a new stream paying
+//!   `share` of the current free balance of the origin on every payment instead of a fixed
+//!   amount. The stream is closed once the balance of the origin reaches `floor`.
+//! * `close_stream(origin, id)` – Settle and close a stream. From the next block on, no more
+//!   funds will be owed.
+//! * `reject_stream(origin, id)` – Close a stream without settling it, returning its funds to
+//!   the source. Can only be called by the target of the stream, while recipients of a split
+//!   stream can reject their own share.
+//! * `set_incoming_policy(origin, policy)` – Accept new streams targeting the origin from any
+//!   source (`AcceptAll`, the default), from no source (`RejectAll`), only from allowed sources
+//!   (`AllowList`) or only after accepting them explicitly (`RequireAcceptance`).
+//! * `accept_stream(origin, id)` – Accept a stream proposed to the origin. Proposals which are not
+//!   accepted within `ProposalLifetime` blocks expire and their deposit is returned.
+//! * `set_allowed_source(origin, source, allowed)` – Add `source` to or remove it from the allow
+//!   list of the origin.
+//! * `withdraw(origin, id)` – Settle a stream, transferring the funds owed to its target. Can
+//!   only be called by the target of the stream.
+//! * `claim(origin, id, beneficiary)` – Settle a stream, transferring the funds owed to
+//!   `beneficiary`. Can only be called by the target of the stream.
+//! * `transfer_stream_target(origin, id, new_target)` – Assign a stream to a new target. Can
+//!   only be called by the target of the stream.
+//! * `transfer_stream_source(origin, id, new_source)` – Propose to hand a stream over to a new
+//!   source, which takes it over together with its deposit by calling
+//!   `accept_stream_source(origin, id, spend_rate, max_funds)` with the terms it expects.
+//! * `update_stream(origin, id, spend_rate, effective_at)` – Change the spend rate of a stream,
+//!   either from the next block on or for the payments due in and after block `effective_at`.
+//! * `top_up_stream(origin, id, amount)` – Add funds to the cap or escrow of a stream.
+//! * `reduce_funding(origin, id, amount)` – Return unspent funds of a capped or escrowed stream
+//!   to its source, as long as the payments already due remain covered.
+//! * `pause_stream(origin, id, resume_at)` – Settle a stream and pause it, keeping its deposit
+//!   and identifier. No payments become due while the stream is paused. The stream is resumed
+//!   automatically after block `resume_at`, if given.
+//! * `resume_stream(origin, id)` – Resume a paused stream.
+//! * `settle_stream(origin, id)` – Settle a stream. Can be called by any account.
 
 #![cfg_attr(not(feature = "std"), no_std)]
 
 mod benchmarking;
+pub mod migrations;
 #[cfg(test)]
 mod mock;
+mod nonfungibles;
 #[cfg(test)]
 mod tests;
 pub mod weights;
 
-use frame_support::traits::{Currency, ReservableCurrency};
+use frame_support::traits::{
+    tokens::fungibles::{Inspect, Mutate, Transfer},
+    Currency, ReservableCurrency,
+};
 use sp_std::prelude::*;
 
 pub use pallet::*;
@@ -37,12 +117,28 @@ pub use weights::WeightInfo;
 type AccountIdOf<T> = <T as frame_system::Config>::AccountId;
 type BalanceOf<T> = <<T as Config>::Currency as Currency<AccountIdOf<T>>>::Balance;
 
+/// Globally unique identifier of a stream.
+pub type StreamId = u64;
+
 #[frame_support::pallet]
 pub mod pallet {
     use super::*;
     use frame_support::pallet_prelude::*;
-    use frame_support::traits::ExistenceRequirement::AllowDeath;
+    use frame_support::sp_runtime::traits::{
+        AccountIdConversion, MaybeSerializeDeserialize, One, Saturating, UniqueSaturatedInto, Zero,
+    };
+    use frame_support::sp_runtime::{PerThing, Perbill, TransactionOutcome};
+    use frame_support::storage::with_transaction;
+    use frame_support::traits::{
+        BalanceStatus, ExistenceRequirement::AllowDeath, OnRuntimeUpgrade,
+    };
+    use frame_support::{log, PalletId};
     use frame_system::pallet_prelude::*;
+    #[cfg(feature = "std")]
+    use serde::{Deserialize, Serialize};
+
+    /// The current storage version.
+    const STORAGE_VERSION: StorageVersion = StorageVersion::new(1);
 
     #[pallet::config]
     pub trait Config: frame_system::Config {
@@ -52,14 +148,51 @@ pub mod pallet {
         /// The currency trait.
         type Currency: ReservableCurrency<Self::AccountId>;
 
+        /// Identifier of an asset which can be streamed besides the native currency.
+        type AssetId: Member + Parameter + Copy + MaxEncodedLen + MaybeSerializeDeserialize;
+
+        /// The fungible assets which can be streamed besides the native currency.
+        type Assets: Inspect<Self::AccountId, AssetId = Self::AssetId, Balance = BalanceOf<Self>>
+            + Mutate<Self::AccountId>
+            + Transfer<Self::AccountId>;
+
         /// The (refundable) deposit required to open a stream.
         #[pallet::constant]
         type StreamDeposit: Get<BalanceOf<Self>>;
 
+        /// The identifier of the pallet, from which the escrow accounts of streams are derived.
+        #[pallet::constant]
+        type PalletId: Get<PalletId>;
+
         /// The maximum number of streams per account.
         #[pallet::constant]
         type MaxStreams: Get<u32>;
 
+        /// The maximum number of recipients sharing the payments of a split stream, besides its
+        /// target.
+        #[pallet::constant]
+        type MaxRecipients: Get<u32>;
+
+        /// The maximum number of streams paying a single account, as their target or as a
+        /// recipient of their shares.
+        #[pallet::constant]
+        type MaxIncomingStreams: Get<u32>;
+
+        /// The maximum number of streams settled automatically at the beginning of a block. A
+        /// split stream pays each of its up to `MaxRecipients` recipients separately, which
+        /// should be taken into account when choosing this limit.
+        #[pallet::constant]
+        type MaxStreamsPerBlock: Get<u32>;
+
+        /// The hook in which streams are settled automatically.
+        #[pallet::constant]
+        type SettlementHook: Get<SettlementHook>;
+
+        /// The number of blocks in which a stream proposed to a target requiring acceptance
+        /// must be accepted.
+        #[pallet::constant]
+        type ProposalLifetime: Get<Self::BlockNumber>;
+
         /// Information on runtime weights.
         type WeightInfo: WeightInfo;
     }
@@ -67,16 +200,119 @@ pub mod pallet {
     #[pallet::event]
     #[pallet::generate_deposit(pub(super) fn deposit_event)]
     pub enum Event<T: Config> {
-        /// A new stream was successfully opened. [source, target, spend_rate]
-        StreamOpened(T::AccountId, T::AccountId, BalanceOf<T>),
-        /// A stream was successfully closed. [source, target, spend_rate]
-        StreamClosed(T::AccountId, T::AccountId, BalanceOf<T>),
-        /// A stream was automatically closed due to insufficient funds. [source, target, amount]
-        StreamExhausted(T::AccountId, T::AccountId, BalanceOf<T>),
-        /// A payment was made by a stream. [source, target, amount]
-        PaymentMade(T::AccountId, T::AccountId, BalanceOf<T>),
-        /// A payment failed [source, target, amount, reason]
-        PaymentFailed(T::AccountId, T::AccountId, BalanceOf<T>, DispatchError),
+        /// A new stream was successfully opened. [id, source, target, asset, spend_rate]
+        StreamOpened(
+            StreamId,
+            T::AccountId,
+            T::AccountId,
+            StreamAssetOf<T>,
+            BalanceOf<T>,
+        ),
+        /// A stream was successfully closed.
+        /// [id, source, target, spend_rate, total_paid, payments_made]
+        StreamClosed(
+            StreamId,
+            T::AccountId,
+            T::AccountId,
+            BalanceOf<T>,
+            BalanceOf<T>,
+            u32,
+        ),
+        /// A stream was automatically closed due to insufficient funds.
+        /// [id, source, target, amount, total_paid, payments_made]
+        StreamExhausted(
+            StreamId,
+            T::AccountId,
+            T::AccountId,
+            BalanceOf<T>,
+            BalanceOf<T>,
+            u32,
+        ),
+        /// A stream was automatically closed after paying its cap in full, reaching its end
+        /// block or reaching the floor of the balance of its source.
+        /// [id, source, target, spend_rate, total_paid, payments_made]
+        StreamCompleted(
+            StreamId,
+            T::AccountId,
+            T::AccountId,
+            BalanceOf<T>,
+            BalanceOf<T>,
+            u32,
+        ),
+        /// Payments were made by a stream. [id, source, dest, amount]
+        PaymentMade(StreamId, T::AccountId, T::AccountId, BalanceOf<T>),
+        /// Payments failed [id, source, target, amount, reason]
+        PaymentFailed(
+            StreamId,
+            T::AccountId,
+            T::AccountId,
+            BalanceOf<T>,
+            DispatchError,
+        ),
+        /// The target of a stream claimed the payments due for the blocks after `from` up to
+        /// and including `to`. [id, target, beneficiary, amount, from, to]
+        Claimed(
+            StreamId,
+            T::AccountId,
+            T::AccountId,
+            BalanceOf<T>,
+            T::BlockNumber,
+            T::BlockNumber,
+        ),
+        /// A stream was paused. [id, source, target, resume_at]
+        StreamPaused(StreamId, T::AccountId, T::AccountId, Option<T::BlockNumber>),
+        /// A paused stream was resumed. [id, source, target]
+        StreamResumed(StreamId, T::AccountId, T::AccountId),
+        /// A new stream was proposed to a target requiring acceptance.
+        /// [id, source, target, asset, spend_rate, expires_at]
+        StreamProposed(
+            StreamId,
+            T::AccountId,
+            T::AccountId,
+            StreamAssetOf<T>,
+            BalanceOf<T>,
+            T::BlockNumber,
+        ),
+        /// A proposed stream was accepted by its target. [id, source, target]
+        StreamAccepted(StreamId, T::AccountId, T::AccountId),
+        /// A proposed stream was not accepted by its target in time and was closed.
+        /// [id, source, target]
+        StreamProposalExpired(StreamId, T::AccountId, T::AccountId),
+        /// The cap or escrow of a stream was increased. [id, source, amount]
+        StreamFunded(StreamId, T::AccountId, BalanceOf<T>),
+        /// Unspent funds of a capped or escrowed stream were returned to its source.
+        /// [id, source, amount]
+        StreamDefunded(StreamId, T::AccountId, BalanceOf<T>),
+        /// The escrow of a closed stream could not be returned to its source and was left in
+        /// the escrow account. [id, source, amount, reason]
+        EscrowRefundFailed(StreamId, T::AccountId, BalanceOf<T>, DispatchError),
+        /// The target of a stream assigned it to a new target. [id, source, old_target, new_target]
+        StreamTargetTransferred(StreamId, T::AccountId, T::AccountId, T::AccountId),
+        /// The source of a stream proposed to hand it over to a new source.
+        /// [id, source, new_source]
+        StreamSourceTransferProposed(StreamId, T::AccountId, T::AccountId),
+        /// A stream was taken over by a new source. [id, old_source, new_source]
+        StreamSourceTransferred(StreamId, T::AccountId, T::AccountId),
+        /// A stream was rejected by its target. [id, source, target]
+        StreamRejected(StreamId, T::AccountId, T::AccountId),
+        /// A recipient of a split stream rejected its share, which is paid to the target from
+        /// then on. [id, source, recipient]
+        ShareRejected(StreamId, T::AccountId, T::AccountId),
+        /// An account changed its policy towards incoming streams. [account, policy]
+        IncomingPolicySet(T::AccountId, IncomingPolicy),
+        /// An account allowed or disallowed a source to open streams targeting it.
+        /// [account, source, allowed]
+        AllowedSourceSet(T::AccountId, T::AccountId, bool),
+        /// The spend rate of a stream was changed, applying to the payments due in and after
+        /// block `effective_at`. [id, source, target, old_rate, new_rate, effective_at]
+        StreamUpdated(
+            StreamId,
+            T::AccountId,
+            T::AccountId,
+            BalanceOf<T>,
+            BalanceOf<T>,
+            T::BlockNumber,
+        ),
     }
 
     /// Error for the stream-payments pallet.
@@ -89,19 +325,208 @@ pub mod pallet {
         ReflexiveStream,
         /// Cannot create a stream with higher spend rate than account's available balance.
         InsufficientBalance,
-        /// Stream with given origin/index does not exist.
+        /// Stream with given origin/id does not exist.
         StreamNotFound,
+        /// Cannot create a stream ending in the current or an earlier block.
+        InvalidEndBlock,
+        /// Cannot create a stream with a payment period of zero blocks.
+        InvalidPeriod,
+        /// Cannot create a stream with a cliff in the current or an earlier block, or after its
+        /// end block.
+        InvalidCliffBlock,
+        /// The origin is not the target of the stream (or, where allowed, one of its recipients).
+        NotStreamTarget,
+        /// Capped streams can only be opened in the native currency, with a fixed rate and
+        /// without escrow. Caps are reserved on the source and `Assets` provides no reserves,
+        /// so streams of other assets are bounded with an escrow instead.
+        UnsupportedCap,
+        /// The stream is neither capped nor escrowed.
+        NotFunded,
+        /// The stream does not have enough unspent funds.
+        InsufficientFunding,
+        /// The stream is already paused.
+        AlreadyPaused,
+        /// The stream is not paused.
+        NotPaused,
+        /// Cannot resume a stream automatically in the current or an earlier block.
+        InvalidResumeBlock,
+        /// Cannot change the spend rate of a stream in the current or an earlier block.
+        InvalidEffectiveBlock,
+        /// The stream has not been accepted by its target.
+        StreamPending,
+        /// The stream has already been accepted by its target or did not require acceptance.
+        NotPending,
+        /// The stream proposal has expired.
+        ProposalExpired,
+        /// The stream does not pay a fixed rate.
+        NotFixedRate,
+        /// Cannot split a stream between more than `MaxRecipients` recipients besides the target.
+        TooManyRecipients,
+        /// The shares of the recipients of a split stream add up to more than 100%.
+        InvalidShares,
+        /// The target does not accept streams from the origin.
+        StreamNotAccepted,
+        /// The stream has not been proposed to be transferred to the origin.
+        NoSourceTransfer,
+        /// The target has reached the limit of incoming streams.
+        IncomingStreamLimitReached,
+        /// The stream no longer matches the terms under which its transfer was accepted.
+        SourceTransferTermsChanged,
+    }
+
+    /// The asset paid by a stream.
+    #[derive(Clone, Copy, Encode, Decode, Eq, PartialEq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+    pub enum StreamAsset<AssetId> {
+        /// The native currency.
+        Native,
+        /// An asset provided by `Assets`.
+        Asset(AssetId),
+    }
+
+    impl<AssetId> Default for StreamAsset<AssetId> {
+        fn default() -> Self {
+            StreamAsset::Native
+        }
+    }
+
+    pub(super) type StreamAssetOf<T> = StreamAsset<<T as Config>::AssetId>;
+
+    /// The amount paid by a stream on every payment.
+    #[derive(Clone, Copy, Encode, Decode, Eq, PartialEq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+    pub enum StreamRate<Balance> {
+        /// Pay `spend_rate` of the stream.
+        Fixed,
+        /// Pay `share` of the current free balance of the source, leaving at least `floor`.
+        /// The stream is closed once the balance of the source reaches `floor`.
+        Share { share: Perbill, floor: Balance },
+    }
+
+    impl<Balance> Default for StreamRate<Balance> {
+        fn default() -> Self {
+            StreamRate::Fixed
+        }
     }
 
     #[derive(
         Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, Default, MaxEncodedLen, TypeInfo,
     )]
-    pub struct Stream<AccountId, Balance> {
+    #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+    pub struct Stream<AccountId, AssetId, Balance, BlockNumber> {
+        pub id: StreamId,
         pub target: AccountId,
+        /// The asset paid by the stream.
+        pub asset: StreamAsset<AssetId>,
+        /// The amount paid on every payment by streams with a fixed rate, zero otherwise.
         pub spend_rate: Balance,
+        /// Whether the stream pays a fixed amount or a share of the balance of the source.
+        pub rate: StreamRate<Balance>,
+        /// A spend rate applying to the payments due in and after the given block.
+        pub pending_rate: Option<(BlockNumber, Balance)>,
+        /// The amount left to be paid by a capped stream. It is kept reserved on the source
+        /// account and the stream is completed once it has been paid in full.
+        pub remaining: Option<Balance>,
+        /// The block in which the stream was opened.
+        pub started_at: BlockNumber,
+        /// The last block in which the stream makes a payment, if any.
+        pub ends_at: Option<BlockNumber>,
+        /// The block before which no payments are made, if any. All payments due up to the
+        /// cliff are made at once in the cliff block.
+        pub cliff: Option<BlockNumber>,
+        /// The number and total amount of the payments which have become due before the cliff
+        /// at an earlier spend rate or before a pause. They are made in the cliff block, before
+        /// the others due.
+        pub accrued: (u32, Balance),
+        /// The total amount transferred by the stream so far.
+        pub total_paid: Balance,
+        /// The number of successful payments made by the stream so far.
+        pub payments_made: u32,
+        /// The number of blocks between subsequent payments.
+        pub period: BlockNumber,
+        /// The number of consecutive missed payments tolerated before the stream is closed.
+        pub grace: u32,
+        /// Whether the stream is paid from its escrow account instead of the free balance of
+        /// the source.
+        pub escrowed: bool,
+        /// The number of consecutive payments missed so far.
+        pub missed: u32,
+        /// The block up to which all due payments have been settled. Streams which have not
+        /// been reached by the automatic settlement lag behind the current block and pay the
+        /// whole backlog once settled.
+        pub last_settled: BlockNumber,
+        /// Whether the stream is active or paused.
+        pub state: StreamState<BlockNumber>,
+    }
+
+    /// The state of a stream.
+    #[derive(Clone, Copy, Encode, Decode, Eq, PartialEq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+    pub enum StreamState<BlockNumber> {
+        /// Payments become due as usual.
+        Active,
+        /// No payments become due until the stream is resumed, either by its source or
+        /// automatically after block `resume_at`.
+        Paused { resume_at: Option<BlockNumber> },
+        /// The stream has been proposed to a target requiring acceptance. No payments become
+        /// due until the target accepts it and it is closed if not accepted before block
+        /// `expires_at`.
+        Pending { expires_at: BlockNumber },
+    }
+
+    impl<BlockNumber> Default for StreamState<BlockNumber> {
+        fn default() -> Self {
+            StreamState::Active
+        }
+    }
+
+    pub(super) type StreamOf<T> = Stream<
+        AccountIdOf<T>,
+        <T as Config>::AssetId,
+        BalanceOf<T>,
+        <T as frame_system::Config>::BlockNumber,
+    >;
+    pub(super) type StreamVec<T> = BoundedVec<StreamOf<T>, <T as Config>::MaxStreams>;
+
+    pub(super) type StreamRateOf<T> = StreamRate<BalanceOf<T>>;
+    pub(super) type SharesOf<T> =
+        BoundedVec<(AccountIdOf<T>, Perbill), <T as Config>::MaxRecipients>;
+    pub(super) type IncomingOf<T> =
+        BoundedVec<(AccountIdOf<T>, StreamId), <T as Config>::MaxIncomingStreams>;
+
+    /// Optional parameters of a new stream.
+    #[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+    pub struct StreamOptions<Balance, BlockNumber> {
+        /// The maximum total amount to be paid by the stream, reserved up front.
+        pub max_amount: Option<Balance>,
+        /// The last block in which the stream makes a payment.
+        pub ends_at: Option<BlockNumber>,
+        /// The block before which no payments are made.
+        pub cliff: Option<BlockNumber>,
+        /// The number of blocks between subsequent payments.
+        pub period: BlockNumber,
+        /// The number of consecutive missed payments tolerated before the stream is closed.
+        pub grace: u32,
+        /// The amount moved to the escrow account of the stream, from which it is paid.
+        pub escrow: Option<Balance>,
+    }
+
+    impl<Balance, BlockNumber: One> Default for StreamOptions<Balance, BlockNumber> {
+        fn default() -> Self {
+            Self {
+                max_amount: None,
+                ends_at: None,
+                cliff: None,
+                period: One::one(),
+                grace: 0,
+                escrow: None,
+            }
+        }
     }
 
-    type StreamVec<T> = BoundedVec<Stream<AccountIdOf<T>, BalanceOf<T>>, <T as Config>::MaxStreams>;
+    pub(super) type StreamOptionsOf<T> =
+        StreamOptions<BalanceOf<T>, <T as frame_system::Config>::BlockNumber>;
 
     /// The lookup table for streams.
     #[pallet::storage]
@@ -109,123 +534,1918 @@ pub mod pallet {
     pub(super) type Streams<T: Config> =
         StorageMap<_, Twox64Concat, T::AccountId, StreamVec<T>, ValueQuery>;
 
+    /// The source account of every open stream.
+    #[pallet::storage]
+    #[pallet::getter(fn stream_source)]
+    pub(super) type StreamSources<T: Config> =
+        StorageMap<_, Twox64Concat, StreamId, T::AccountId, OptionQuery>;
+
+    /// The recipients of split streams along with their shares of every payment. The target of
+    /// a split stream receives the rest.
+    #[pallet::storage]
+    #[pallet::getter(fn stream_shares)]
+    pub(super) type StreamShares<T: Config> =
+        StorageMap<_, Twox64Concat, StreamId, SharesOf<T>, OptionQuery>;
+
+    /// The source and identifier of every open stream paying an account, either as its target
+    /// or as a recipient of its shares.
+    #[pallet::storage]
+    #[pallet::getter(fn incoming_streams)]
+    pub(super) type IncomingStreams<T: Config> =
+        StorageMap<_, Twox64Concat, T::AccountId, IncomingOf<T>, ValueQuery>;
+
+    /// The account each stream has been proposed to be transferred to by its source, until
+    /// the account accepts the transfer.
+    #[pallet::storage]
+    #[pallet::getter(fn source_transfer)]
+    pub(super) type SourceTransfers<T: Config> =
+        StorageMap<_, Twox64Concat, StreamId, T::AccountId, OptionQuery>;
+
+    /// The identifier to be assigned to the next opened stream.
+    #[pallet::storage]
+    #[pallet::getter(fn next_stream_id)]
+    pub(super) type NextStreamId<T: Config> = StorageValue<_, StreamId, ValueQuery>;
+
+    /// The last stream settled automatically. Automatic settlement in the next block resumes
+    /// after this stream, or starts over if there is none.
+    #[pallet::storage]
+    #[pallet::getter(fn settlement_cursor)]
+    pub(super) type SettlementCursor<T: Config> = StorageValue<_, StreamId, OptionQuery>;
+
+    /// The policy of every account towards streams targeting it. Accounts without an entry
+    /// accept all streams.
+    #[pallet::storage]
+    #[pallet::getter(fn incoming_policy)]
+    pub(super) type IncomingPolicies<T: Config> =
+        StorageMap<_, Twox64Concat, T::AccountId, IncomingPolicy, ValueQuery>;
+
+    /// The sources allowed to open streams targeting an account with the `AllowList` policy.
+    /// [target, source]
+    #[pallet::storage]
+    #[pallet::getter(fn allowed_source)]
+    pub(super) type AllowedSources<T: Config> = StorageDoubleMap<
+        _,
+        Twox64Concat,
+        T::AccountId,
+        Twox64Concat,
+        T::AccountId,
+        (),
+        OptionQuery,
+    >;
+
+    #[pallet::genesis_config]
+    pub struct GenesisConfig<T: Config> {
+        /// Streams open from the genesis block on.
+        /// [source, target, asset, spend_rate, rate, shares, options]
+        pub streams: Vec<(
+            T::AccountId,
+            T::AccountId,
+            StreamAssetOf<T>,
+            BalanceOf<T>,
+            StreamRateOf<T>,
+            Vec<(T::AccountId, Perbill)>,
+            StreamOptionsOf<T>,
+        )>,
+    }
+
+    #[cfg(feature = "std")]
+    impl<T: Config> Default for GenesisConfig<T> {
+        fn default() -> Self {
+            Self {
+                streams: Vec::new(),
+            }
+        }
+    }
+
+    #[pallet::genesis_build]
+    impl<T: Config> GenesisBuild<T> for GenesisConfig<T> {
+        fn build(&self) {
+            for (source, target, asset, spend_rate, rate, shares, options) in
+                self.streams.iter().cloned()
+            {
+                // Share streams are never split, just like those opened by `open_share_stream`
+                assert!(
+                    rate == StreamRate::Fixed || shares.is_empty(),
+                    "Invalid genesis stream"
+                );
+                Pallet::<T>::do_open_stream(
+                    source, target, asset, spend_rate, rate, shares, options,
+                )
+                .expect("Invalid genesis stream");
+            }
+        }
+    }
+
     #[pallet::pallet]
     #[pallet::generate_store(pub(super) trait Store)]
+    #[pallet::storage_version(STORAGE_VERSION)]
     pub struct Pallet<T>(_);
 
     #[pallet::hooks]
     impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
-        fn on_initialize(_n: T::BlockNumber) -> Weight {
-            let mut num_exhausted_streams: u32 = 0;
-            let mut num_transfers: u32 = 0;
-            <Streams<T>>::translate(|origin, mut streams: StreamVec<T>| {
-                streams.retain(|Stream { target, spend_rate }| {
-                    if T::Currency::free_balance(&origin) < *spend_rate {
-                        Self::deposit_event(Event::StreamExhausted(
-                            origin.clone(),
-                            target.clone(),
-                            *spend_rate,
-                        ));
-                        num_exhausted_streams += 1;
-                        // Return deposit and remove the exhausted stream
-                        T::Currency::unreserve(&origin, T::StreamDeposit::get());
-                        return false;
-                    }
-                    match T::Currency::transfer(&origin, target, *spend_rate, AllowDeath) {
-                        Ok(_) => {
-                            Self::deposit_event(Event::PaymentMade(
-                                origin.clone(),
-                                target.clone(),
-                                *spend_rate,
-                            ));
-                        }
-                        Err(e) => {
-                            Self::deposit_event(Event::PaymentFailed(
-                                origin.clone(),
-                                target.clone(),
-                                *spend_rate,
-                                e,
-                            ));
-                        }
-                    }
-                    num_transfers += 1;
-                    true
-                });
+        fn on_initialize(n: T::BlockNumber) -> Weight {
+            match T::SettlementHook::get() {
+                SettlementHook::OnInitialize => {
+                    Self::settle_streams(n, T::MaxStreamsPerBlock::get())
+                }
+                SettlementHook::OnIdle => 0,
+            }
+        }
 
-                // If there are no more streams we can delete the entry
-                if streams.len() > 0 {
-                    Some(streams)
-                } else {
-                    None
+        fn on_idle(n: T::BlockNumber, remaining_weight: Weight) -> Weight {
+            match T::SettlementHook::get() {
+                SettlementHook::OnInitialize => 0,
+                SettlementHook::OnIdle => {
+                    let max_streams =
+                        Self::streams_within(remaining_weight).min(T::MaxStreamsPerBlock::get());
+                    Self::settle_streams(n, max_streams)
                 }
-            });
-            <T as Config>::WeightInfo::on_initialize_stream_exhausted(num_exhausted_streams)
-                .saturating_add(<T as Config>::WeightInfo::on_initialize_transfer(
-                    num_transfers,
-                ))
+            }
+        }
+
+        fn on_runtime_upgrade() -> Weight {
+            migrations::Migration::<T>::on_runtime_upgrade()
+        }
+
+        #[cfg(feature = "try-runtime")]
+        fn pre_upgrade() -> Result<(), &'static str> {
+            migrations::Migration::<T>::pre_upgrade()
+        }
+
+        #[cfg(feature = "try-runtime")]
+        fn post_upgrade() -> Result<(), &'static str> {
+            migrations::Migration::<T>::post_upgrade()?;
+            Self::do_try_state()
         }
     }
 
     #[pallet::call]
     impl<T: Config> Pallet<T> {
-        /// Open a new stream. From the next block on, on each block `spend_rate` will be
-        /// transferred to the `target` account. The stream can be closed by calling `close_stream`.
+        /// Open a new stream. From the next block on, on each block `spend_rate` of `asset` will
+        /// be owed to the `target` account. The stream can be closed by calling `close_stream`.
+        /// The deposit is reserved in the native currency regardless of `asset`.
+        ///
+        /// The stream is assigned a unique identifier, which is reported in the `StreamOpened`
+        /// event and is used to refer to the stream in subsequent calls.
+        ///
+        /// The stream can be further configured with `options`:
+        /// * If `max_amount` is given, the stream will transfer no more than `max_amount` in
+        ///   total and will be closed automatically once it is reached. The whole `max_amount` is
+        ///   reserved on the origin account up front and payments are made from the reserve.
+        ///   Only streams of the native currency can be capped, since `Assets` provides no
+        ///   reserves. Streams of other assets can be bounded by funding an `escrow` instead.
+        /// * If `ends_at` is given, the last payment becomes due in block `ends_at`, after which
+        ///   the stream is closed automatically.
+        /// * If `cliff` is given, no payments are made before block `cliff`. All payments due up
+        ///   to the cliff are made at once in block `cliff`, followed by the regular ones.
+        /// * With `period` greater than one, `spend_rate` becomes due once every `period` blocks
+        ///   instead of on each block.
+        /// * A payment which cannot be made is skipped. The stream is closed once more than
+        ///   `grace` consecutive payments have been missed.
+        /// * If `escrow` is given, the amount is moved to the escrow account of the stream up
+        ///   front and the stream is paid from it. Other spends of the origin cannot starve such
+        ///   streams. The escrow account can be topped up with `top_up_stream` and the remaining
+        ///   funds are returned to the origin once the stream is closed.
         ///
         /// No more that `T::MaxStreams` streams can be open for a single origin.
         ///
         /// Reflexive (i.e. `source == target`) streams cannot be opened.
         ///
         /// The dispatch origin for this call must be _Signed_.
-        #[pallet::weight(<T as Config>::WeightInfo::open_stream())]
+        #[pallet::weight(
+            <T as Config>::WeightInfo::open_stream()
+                .saturating_add(Pallet::<T>::escrow_weight(options))
+        )]
         pub fn open_stream(
             origin: OriginFor<T>,
             target: AccountIdOf<T>,
+            asset: StreamAssetOf<T>,
+            spend_rate: BalanceOf<T>,
+            options: StreamOptionsOf<T>,
+        ) -> DispatchResult {
+            let source = ensure_signed(origin)?;
+            let rate = StreamRate::Fixed;
+            Self::do_open_stream(source, target, asset, spend_rate, rate, Vec::new(), options)
+        }
+
+        /// Open a new split stream. Same as `open_stream`, but every payment is split between
+        /// the `target` and the other recipients according to `shares`. Each recipient receives
+        /// its share of the payment, rounded down, and the target receives the rest.
+        ///
+        /// The shares must add up to no more than 100% and there can be no more than
+        /// `T::MaxRecipients` recipients besides the target. All recipients must accept streams
+        /// from the origin without requiring explicit acceptance.
+        ///
+        /// The dispatch origin for this call must be _Signed_.
+        #[pallet::weight(
+            <T as Config>::WeightInfo::open_split_stream(shares.len() as u32)
+                .saturating_add(Pallet::<T>::escrow_weight(options))
+        )]
+        pub fn open_split_stream(
+            origin: OriginFor<T>,
+            target: AccountIdOf<T>,
+            asset: StreamAssetOf<T>,
+            spend_rate: BalanceOf<T>,
+            shares: Vec<(AccountIdOf<T>, Perbill)>,
+            options: StreamOptionsOf<T>,
+        ) -> DispatchResult {
+            let source = ensure_signed(origin)?;
+            let rate = StreamRate::Fixed;
+            Self::do_open_stream(source, target, asset, spend_rate, rate, shares, options)
+        }
+
+        /// Open a new stream paying a share of the balance of the origin. Same as `open_stream`,
+        /// but every payment amounts to `share` of the current free balance of `asset` of the
+        /// origin, as long as at least `floor` is left. Once the balance of the origin reaches
+        /// `floor`, the stream is closed.
+        ///
+        /// Such streams are never exhausted, but cannot be capped with `max_amount`.
+        ///
+        /// The dispatch origin for this call must be _Signed_.
+        #[pallet::weight(
+            <T as Config>::WeightInfo::open_stream()
+                .saturating_add(Pallet::<T>::escrow_weight(options))
+        )]
+        pub fn open_share_stream(
+            origin: OriginFor<T>,
+            target: AccountIdOf<T>,
+            asset: StreamAssetOf<T>,
+            share: Perbill,
+            floor: BalanceOf<T>,
+            options: StreamOptionsOf<T>,
+        ) -> DispatchResult {
+            let source = ensure_signed(origin)?;
+            let rate = StreamRate::Share { share, floor };
+            let spend_rate = Zero::zero();
+            Self::do_open_stream(source, target, asset, spend_rate, rate, Vec::new(), options)
+        }
+
+        /// Close a stream. The stream is settled first, so the target receives all payments
+        /// which have become due up to the current block. From the next block on, no more
+        /// funds will be owed.
+        ///
+        /// The stream is identified by the `id` assigned to it when it was opened. The deposit
+        /// and, for capped streams, the unspent part of the cap are returned to the origin.
+        ///
+        /// The dispatch origin for this call must be _Signed_ by the source of the stream.
+        #[pallet::weight(<T as Config>::WeightInfo::close_stream(0, T::MaxRecipients::get()))]
+        pub fn close_stream(origin: OriginFor<T>, id: StreamId) -> DispatchResultWithPostInfo {
+            let source = ensure_signed(origin)?;
+            let now = <frame_system::Pallet<T>>::block_number();
+            let recipients = Self::num_recipients(id);
+            Self::try_mutate_stream(&source, id, |stream| {
+                // The stream could have been closed already while being settled
+                if !Self::settle(&source, stream, now, None).is_closed() {
+                    Self::release_funds(&source, stream);
+                    Self::deposit_event(Event::StreamClosed(
+                        id,
+                        source.clone(),
+                        stream.target.clone(),
+                        stream.spend_rate,
+                        stream.total_paid,
+                        stream.payments_made,
+                    ));
+                }
+                Ok::<_, DispatchError>(((), true))
+            })?;
+            Ok(Some(<T as Config>::WeightInfo::close_stream(0, recipients)).into())
+        }
+
+        /// Accept a stream proposed to the origin. From the next block on, payments become due
+        /// as if the stream was opened in the current block.
+        ///
+        /// The dispatch origin for this call must be _Signed_ by the target of the stream.
+        #[pallet::weight(<T as Config>::WeightInfo::accept_stream())]
+        pub fn accept_stream(origin: OriginFor<T>, id: StreamId) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            let source = Self::stream_source(id).ok_or(Error::<T>::StreamNotFound)?;
+            let now = <frame_system::Pallet<T>>::block_number();
+            Self::try_mutate_stream(&source, id, |stream| {
+                ensure!(stream.target == who, Error::<T>::NotStreamTarget);
+                match stream.state {
+                    StreamState::Pending { expires_at } if now >= expires_at => {
+                        return Err(Error::<T>::ProposalExpired.into())
+                    }
+                    StreamState::Pending { .. } => (),
+                    _ => return Err(Error::<T>::NotPending.into()),
+                }
+                stream.state = StreamState::Active;
+                stream.started_at = now;
+                stream.last_settled = now;
+                Self::deposit_event(Event::StreamAccepted(id, source.clone(), who));
+                // The stream could have reached its end while pending
+                let outcome = Self::settle(&source, stream, now, None);
+                Ok::<_, DispatchError>(((), outcome.is_closed()))
+            })
+        }
+
+        /// Reject a stream. The stream is closed without being settled, so payments which have
+        /// become due since it was last settled are not made. The deposit and, for capped
+        /// streams, the unspent part of the cap are returned to the source.
+        ///
+        /// A recipient of a split stream rejects only its own share. The stream stays open and
+        /// the target receives the share from then on, including the payments which have become
+        /// due since the stream was last settled.
+        ///
+        /// The dispatch origin for this call must be _Signed_ by the target of the stream or by
+        /// one of its recipients.
+        #[pallet::weight(<T as Config>::WeightInfo::reject_stream(T::MaxRecipients::get()))]
+        pub fn reject_stream(origin: OriginFor<T>, id: StreamId) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            let source = Self::stream_source(id).ok_or(Error::<T>::StreamNotFound)?;
+            Self::try_mutate_stream(&source, id, |stream| {
+                if stream.target != who {
+                    Self::remove_share(id, &who)?;
+                    Self::deposit_event(Event::ShareRejected(id, source.clone(), who));
+                    return Ok::<_, DispatchError>(((), false));
+                }
+                Self::release_funds(&source, stream);
+                Self::deposit_event(Event::StreamRejected(id, source.clone(), who));
+                Ok::<_, DispatchError>(((), true))
+            })
+        }
+
+        /// Set the policy of the origin towards new streams targeting it. Streams which are
+        /// already open are not affected.
+        ///
+        /// The dispatch origin for this call must be _Signed_.
+        #[pallet::weight(<T as Config>::WeightInfo::set_incoming_policy())]
+        pub fn set_incoming_policy(origin: OriginFor<T>, policy: IncomingPolicy) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            if policy == IncomingPolicy::default() {
+                <IncomingPolicies<T>>::remove(&who);
+            } else {
+                <IncomingPolicies<T>>::insert(&who, policy);
+            }
+            Self::deposit_event(Event::IncomingPolicySet(who, policy));
+            Ok(())
+        }
+
+        /// Allow or disallow `source` to open streams targeting the origin. The allow list only
+        /// applies while the origin has the `AllowList` policy.
+        ///
+        /// The dispatch origin for this call must be _Signed_.
+        #[pallet::weight(<T as Config>::WeightInfo::set_allowed_source())]
+        pub fn set_allowed_source(
+            origin: OriginFor<T>,
+            source: AccountIdOf<T>,
+            allowed: bool,
+        ) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            if allowed {
+                <AllowedSources<T>>::insert(&who, &source, ());
+            } else {
+                <AllowedSources<T>>::remove(&who, &source);
+            }
+            Self::deposit_event(Event::AllowedSourceSet(who, source, allowed));
+            Ok(())
+        }
+
+        /// Withdraw the funds owed by a stream, i.e. settle the stream making all payments which
+        /// have become due since it was last settled. Same as `claim` with the origin as the
+        /// beneficiary.
+        ///
+        /// The dispatch origin for this call must be _Signed_ by the target of the stream.
+        #[pallet::weight(<T as Config>::WeightInfo::withdraw(T::MaxRecipients::get()))]
+        pub fn withdraw(origin: OriginFor<T>, id: StreamId) -> DispatchResultWithPostInfo {
+            let who = ensure_signed(origin)?;
+            let recipients = Self::num_recipients(id);
+            Self::do_claim(who.clone(), id, who)?;
+            Ok(Some(<T as Config>::WeightInfo::withdraw(recipients)).into())
+        }
+
+        /// Claim the funds owed by a stream, i.e. settle the stream transferring all payments
+        /// which have become due since it was last settled to `beneficiary`.
+        ///
+        /// The balance of the source is checked at the time of the claim. Payments which the
+        /// source cannot afford are missed and the stream is closed as exhausted once it has
+        /// missed more than `grace` consecutive payments.
+        ///
+        /// The dispatch origin for this call must be _Signed_ by the target of the stream.
+        #[pallet::weight(<T as Config>::WeightInfo::claim(T::MaxRecipients::get()))]
+        pub fn claim(
+            origin: OriginFor<T>,
+            id: StreamId,
+            beneficiary: AccountIdOf<T>,
+        ) -> DispatchResultWithPostInfo {
+            let who = ensure_signed(origin)?;
+            let recipients = Self::num_recipients(id);
+            Self::do_claim(who, id, beneficiary)?;
+            Ok(Some(<T as Config>::WeightInfo::claim(recipients)).into())
+        }
+
+        /// Assign a stream to `new_target`, e.g. to sell its future payments. The stream is
+        /// settled first, so the payments which have already become due are made to the current
+        /// target. `new_target` must accept streams from the source without explicit acceptance.
+        ///
+        /// The dispatch origin for this call must be _Signed_ by the target of the stream.
+        #[pallet::weight(<T as Config>::WeightInfo::transfer_stream_target())]
+        pub fn transfer_stream_target(
+            origin: OriginFor<T>,
+            id: StreamId,
+            new_target: AccountIdOf<T>,
+        ) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            Self::do_transfer_target(who, id, new_target)
+        }
+
+        /// Propose to hand a stream over to `new_source`. The stream is transferred once
+        /// `new_source` accepts it with `accept_stream_source`, replacing any earlier proposal.
+        ///
+        /// The dispatch origin for this call must be _Signed_ by the source of the stream.
+        #[pallet::weight(<T as Config>::WeightInfo::transfer_stream_source())]
+        pub fn transfer_stream_source(
+            origin: OriginFor<T>,
+            id: StreamId,
+            new_source: AccountIdOf<T>,
+        ) -> DispatchResult {
+            let source = ensure_signed(origin)?;
+            Self::try_mutate_stream(&source, id, |stream| {
+                ensure!(stream.target != new_source, Error::<T>::ReflexiveStream);
+                <SourceTransfers<T>>::insert(id, &new_source);
+                Self::deposit_event(Event::StreamSourceTransferProposed(
+                    id,
+                    source.clone(),
+                    new_source,
+                ));
+                Ok::<_, DispatchError>(((), false))
+            })
+        }
+
+        /// Take over a stream proposed to the origin with `transfer_stream_source`. The stream is
+        /// settled by its current source first. Its deposit (and, for capped streams, the unspent
+        /// part of the cap) is then returned to the current source and reserved from the origin,
+        /// which pays the stream from now on. For escrowed streams the origin pays the current
+        /// source the balance left in the escrow account, which is refunded to the origin once the
+        /// stream is closed.
+        ///
+        /// The stream is only taken over if it still pays `spend_rate`, including any scheduled
+        /// rate change, and the unspent cap and escrow balance taken over add up to no more than
+        /// `max_funds`, so that the current source cannot change the terms after proposing the
+        /// transfer. The target and the other recipients of the stream must accept streams from
+        /// the origin without explicit acceptance.
+        ///
+        /// The dispatch origin for this call must be _Signed_ by the proposed new source.
+        #[pallet::weight(
+            <T as Config>::WeightInfo::accept_stream_source(T::MaxRecipients::get())
+        )]
+        pub fn accept_stream_source(
+            origin: OriginFor<T>,
+            id: StreamId,
+            spend_rate: BalanceOf<T>,
+            max_funds: BalanceOf<T>,
+        ) -> DispatchResult {
+            let new_source = ensure_signed(origin)?;
+            let source = Self::stream_source(id).ok_or(Error::<T>::StreamNotFound)?;
+            ensure!(
+                Self::source_transfer(id).as_ref() == Some(&new_source),
+                Error::<T>::NoSourceTransfer
+            );
+            let now = <frame_system::Pallet<T>>::block_number();
+
+            // Settlement must be reverted if the new source cannot take the stream over
+            with_transaction(|| {
+                match Self::do_transfer_source(source, new_source, id, spend_rate, max_funds, now) {
+                    Ok(()) => TransactionOutcome::Commit(Ok(())),
+                    Err(e) => TransactionOutcome::Rollback(Err(e)),
+                }
+            })
+        }
+
+        /// Change the spend rate of a stream, keeping its identifier and statistics.
+        ///
+        /// The stream is settled at the old rate first. If `effective_at` is given, the payments
+        /// due in and after block `effective_at` are made at `spend_rate`, replacing any change
+        /// scheduled before. Otherwise `spend_rate` applies from the next block on. Payments
+        /// which have become due before the cliff of the stream at the old rate are still made at
+        /// that rate in the cliff block.
+        ///
+        /// Like when opening a stream, the free balance of the origin must cover a payment at
+        /// `spend_rate`, unless the stream is capped.
+        ///
+        /// The dispatch origin for this call must be _Signed_ by the source of the stream.
+        #[pallet::weight(<T as Config>::WeightInfo::update_stream())]
+        pub fn update_stream(
+            origin: OriginFor<T>,
+            id: StreamId,
             spend_rate: BalanceOf<T>,
+            effective_at: Option<T::BlockNumber>,
+        ) -> DispatchResult {
+            let source = ensure_signed(origin)?;
+            let now = <frame_system::Pallet<T>>::block_number();
+            if effective_at.map_or(false, |effective_at| effective_at <= now) {
+                return Err(Error::<T>::InvalidEffectiveBlock.into());
+            }
+
+            Self::try_mutate_stream(&source, id, |stream| {
+                ensure!(stream.rate == StreamRate::Fixed, Error::<T>::NotFixedRate);
+                // Capped streams are paid from the reserve, so only the cap matters
+                ensure!(
+                    stream.remaining.is_some()
+                        || Self::free_balance(stream.asset, &Self::payer(&source, stream))
+                            >= spend_rate,
+                    Error::<T>::InsufficientBalance
+                );
+
+                // Settle at the old rate, including any change scheduled before which is due
+                if Self::settle(&source, stream, now, None).is_closed() {
+                    return Ok::<_, DispatchError>(((), true));
+                }
+                let old_rate = stream.spend_rate;
+                let effective_at = match effective_at {
+                    Some(effective_at) => {
+                        stream.pending_rate = Some((effective_at, spend_rate));
+                        effective_at
+                    }
+                    None => {
+                        // Payments due before the cliff are kept at the old rate
+                        if Self::before_cliff(stream, now) {
+                            Self::accrue(stream, now);
+                        }
+                        stream.spend_rate = spend_rate;
+                        stream.pending_rate = None;
+                        now.saturating_add(One::one())
+                    }
+                };
+                Self::deposit_event(Event::StreamUpdated(
+                    id,
+                    source.clone(),
+                    stream.target.clone(),
+                    old_rate,
+                    spend_rate,
+                    effective_at,
+                ));
+                Ok(((), false))
+            })
+        }
+
+        /// Add `amount` to the funds of a capped or escrowed stream. For capped streams the
+        /// amount is reserved and added to the remaining cap, for escrowed streams it is moved to
+        /// the escrow account of the stream.
+        ///
+        /// The dispatch origin for this call must be _Signed_ by the source of the stream.
+        #[pallet::weight(<T as Config>::WeightInfo::top_up_stream())]
+        pub fn top_up_stream(
+            origin: OriginFor<T>,
+            id: StreamId,
+            amount: BalanceOf<T>,
+        ) -> DispatchResult {
+            let source = ensure_signed(origin)?;
+            Self::try_mutate_stream(&source, id, |stream| {
+                if let Some(remaining) = stream.remaining {
+                    T::Currency::reserve(&source, amount)?;
+                    stream.remaining = Some(remaining.saturating_add(amount));
+                } else if stream.escrowed {
+                    Self::transfer(stream.asset, &source, &Self::escrow_account(id), amount)?;
+                } else {
+                    return Err(Error::<T>::NotFunded.into());
+                }
+                Self::deposit_event(Event::StreamFunded(id, source.clone(), amount));
+                Ok::<_, DispatchError>(((), false))
+            })
+        }
+
+        /// Return `amount` of the unspent funds of a capped or escrowed stream to the origin. The
+        /// stream is settled first, so the payments which have already become due remain covered.
+        ///
+        /// The dispatch origin for this call must be _Signed_ by the source of the stream.
+        #[pallet::weight(<T as Config>::WeightInfo::reduce_funding())]
+        pub fn reduce_funding(
+            origin: OriginFor<T>,
+            id: StreamId,
+            amount: BalanceOf<T>,
+        ) -> DispatchResult {
+            let source = ensure_signed(origin)?;
+            let now = <frame_system::Pallet<T>>::block_number();
+            // Settlement must be reverted if the funds turn out to be insufficient
+            with_transaction(|| {
+                let result = Self::try_mutate_stream(&source, id, |stream| {
+                    ensure!(
+                        stream.remaining.is_some() || stream.escrowed,
+                        Error::<T>::NotFunded
+                    );
+                    // The stream could be closed while being settled
+                    if Self::settle(&source, stream, now, None).is_closed() {
+                        return Ok::<_, DispatchError>(((), true));
+                    }
+                    if let Some(remaining) = stream.remaining {
+                        ensure!(amount <= remaining, Error::<T>::InsufficientFunding);
+                        T::Currency::unreserve(&source, amount);
+                        stream.remaining = Some(remaining.saturating_sub(amount));
+                    } else {
+                        let escrow = Self::escrow_account(id);
+                        ensure!(
+                            amount <= Self::free_balance(stream.asset, &escrow),
+                            Error::<T>::InsufficientFunding
+                        );
+                        Self::transfer(stream.asset, &escrow, &source, amount)?;
+                    }
+                    Self::deposit_event(Event::StreamDefunded(id, source.clone(), amount));
+                    Ok(((), false))
+                });
+                match result {
+                    Ok(()) => TransactionOutcome::Commit(Ok(())),
+                    Err(e) => TransactionOutcome::Rollback(Err(e)),
+                }
+            })
+        }
+
+        /// Pause a stream. The stream is settled first, so the target receives all payments
+        /// which have become due up to the current block. No more payments become due until the
+        /// stream is resumed, while its deposit, identifier and statistics are kept.
+        ///
+        /// If `resume_at` is given, the stream is resumed automatically after block `resume_at`.
+        /// Resuming does not shift the payment schedule: payments stay aligned to the block in
+        /// which the stream was opened, so the first payment after the pause becomes due at the
+        /// next period boundary and is made in full, even if part of its period was paused.
+        ///
+        /// The dispatch origin for this call must be _Signed_ by the source of the stream.
+        #[pallet::weight(<T as Config>::WeightInfo::pause_stream())]
+        pub fn pause_stream(
+            origin: OriginFor<T>,
+            id: StreamId,
+            resume_at: Option<T::BlockNumber>,
         ) -> DispatchResult {
             let source = ensure_signed(origin)?;
+            let now = <frame_system::Pallet<T>>::block_number();
+            if resume_at.map_or(false, |resume_at| resume_at <= now) {
+                return Err(Error::<T>::InvalidResumeBlock.into());
+            }
+
+            Self::try_mutate_stream(&source, id, |stream| {
+                match stream.state {
+                    StreamState::Active => (),
+                    StreamState::Paused { .. } => return Err(Error::<T>::AlreadyPaused.into()),
+                    StreamState::Pending { .. } => return Err(Error::<T>::StreamPending.into()),
+                }
+                // The stream could be closed while being settled
+                if Self::settle(&source, stream, now, None).is_closed() {
+                    return Ok::<_, DispatchError>(((), true));
+                }
+                // Payments due before the cliff are kept, as resuming skips the paused blocks
+                if Self::before_cliff(stream, now) {
+                    Self::accrue(stream, now);
+                }
+                stream.state = StreamState::Paused { resume_at };
+                Self::deposit_event(Event::StreamPaused(
+                    id,
+                    source.clone(),
+                    stream.target.clone(),
+                    resume_at,
+                ));
+                Ok(((), false))
+            })
+        }
+
+        /// Resume a paused stream. From the next block on, payments become due again.
+        ///
+        /// The dispatch origin for this call must be _Signed_ by the source of the stream.
+        #[pallet::weight(<T as Config>::WeightInfo::resume_stream())]
+        pub fn resume_stream(origin: OriginFor<T>, id: StreamId) -> DispatchResult {
+            let source = ensure_signed(origin)?;
+            let now = <frame_system::Pallet<T>>::block_number();
+            Self::try_mutate_stream(&source, id, |stream| {
+                ensure!(
+                    matches!(stream.state, StreamState::Paused { .. }),
+                    Error::<T>::NotPaused
+                );
+                // The stream could have reached its end or resumed automatically meanwhile
+                if Self::settle(&source, stream, now, None).is_closed() {
+                    return Ok::<_, DispatchError>(((), true));
+                }
+                if matches!(stream.state, StreamState::Paused { .. }) {
+                    Self::resume(&source, stream, now);
+                }
+                Ok(((), false))
+            })
+        }
+
+        /// Settle a stream, making all payments which have become due since it was last
+        /// settled. Streams which have run out of funds or reached their end are closed.
+        ///
+        /// The dispatch origin for this call must be _Signed_. Any account can settle a stream.
+        #[pallet::weight(
+            <T as Config>::WeightInfo::settle_stream(T::MaxRecipients::get())
+                .max(<T as Config>::WeightInfo::settle_share_stream())
+        )]
+        pub fn settle_stream(origin: OriginFor<T>, id: StreamId) -> DispatchResultWithPostInfo {
+            ensure_signed(origin)?;
+            let source = Self::stream_source(id).ok_or(Error::<T>::StreamNotFound)?;
+            let now = <frame_system::Pallet<T>>::block_number();
+            let recipients = Self::num_recipients(id);
+            let share = Self::try_mutate_stream(&source, id, |stream| {
+                let share = matches!(stream.rate, StreamRate::Share { .. });
+                let outcome = Self::settle(&source, stream, now, None);
+                Ok::<_, DispatchError>((share, outcome.is_closed()))
+            })?;
+            let weight = if share {
+                <T as Config>::WeightInfo::settle_share_stream()
+            } else {
+                <T as Config>::WeightInfo::settle_stream(recipients)
+            };
+            Ok(Some(weight).into())
+        }
+    }
+
+    /// The hook in which streams are settled automatically.
+    #[derive(Clone, Copy, Encode, Decode, Eq, PartialEq, RuntimeDebug, TypeInfo)]
+    pub enum SettlementHook {
+        /// Settle streams at the beginning of every block, before any extrinsics.
+        OnInitialize,
+        /// Settle streams at the end of every block, using only the weight left.
+        OnIdle,
+    }
+
+    /// The policy of an account towards streams targeting it.
+    #[derive(Clone, Copy, Encode, Decode, Eq, PartialEq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+    pub enum IncomingPolicy {
+        /// Accept streams from any source.
+        AcceptAll,
+        /// Do not accept any streams.
+        RejectAll,
+        /// Accept streams only from the sources in `AllowedSources`.
+        AllowList,
+        /// Accept streams from any source, but only once the account has accepted them
+        /// explicitly.
+        RequireAcceptance,
+    }
+
+    impl Default for IncomingPolicy {
+        fn default() -> Self {
+            IncomingPolicy::AcceptAll
+        }
+    }
+
+    /// Result of settling a stream.
+    enum PaymentOutcome {
+        /// No payment was due from the stream.
+        NotDue,
+        /// The stream remains open.
+        Continued,
+        /// The stream has paid its cap in full or reached its end block and has been closed.
+        Completed,
+        /// The stream could not be paid and has been closed.
+        Exhausted,
+        /// The stream was not accepted by its target in time and has been closed.
+        Expired,
+    }
+
+    impl PaymentOutcome {
+        /// Whether the stream has been closed and should be removed.
+        fn is_closed(&self) -> bool {
+            matches!(
+                self,
+                PaymentOutcome::Completed | PaymentOutcome::Exhausted | PaymentOutcome::Expired
+            )
+        }
+    }
+
+    impl<T: Config> Pallet<T> {
+        /// The number of streams which can be settled automatically within `weight`, assuming
+        /// the most expensive outcome for every stream and that every stream is split between
+        /// the maximum number of recipients.
+        fn streams_within(weight: Weight) -> u32 {
+            let transfer_base = <T as Config>::WeightInfo::on_initialize_transfer(0, 0);
+            let share_base = <T as Config>::WeightInfo::on_initialize_share_payment(0);
+            let exhausted_base = <T as Config>::WeightInfo::on_initialize_stream_exhausted(0);
+            let base = transfer_base.max(share_base).max(exhausted_base);
+            let per_stream =
+                <T as Config>::WeightInfo::on_initialize_transfer(1, T::MaxRecipients::get())
+                    .saturating_sub(transfer_base)
+                    .max(
+                        <T as Config>::WeightInfo::on_initialize_share_payment(1)
+                            .saturating_sub(share_base),
+                    )
+                    .max(
+                        <T as Config>::WeightInfo::on_initialize_stream_exhausted(1)
+                            .saturating_sub(exhausted_base),
+                    )
+                    .max(1);
+            (weight.saturating_sub(base) / per_stream).unique_saturated_into()
+        }
+
+        /// Settle up to `max_streams` streams in block `now`, resuming after the stream settled
+        /// last by the previous call. Returns the weight consumed.
+        pub(crate) fn settle_streams(now: T::BlockNumber, max_streams: u32) -> Weight {
+            if max_streams == 0 {
+                return 0;
+            }
+
+            let iter = match Self::settlement_cursor() {
+                Some(cursor) => {
+                    <StreamSources<T>>::iter_from(<StreamSources<T>>::hashed_key_for(cursor))
+                }
+                None => <StreamSources<T>>::iter(),
+            };
+            let batch: Vec<_> = iter.take(max_streams as usize).collect();
+            // Start over in the next block once all streams have been reached
+            match batch.last() {
+                Some((id, _)) if batch.len() == max_streams as usize => {
+                    <SettlementCursor<T>>::put(id)
+                }
+                _ => <SettlementCursor<T>>::kill(),
+            }
+
+            let mut num_exhausted_streams: u32 = 0;
+            let mut num_transfers: u32 = 0;
+            let mut num_recipients: u32 = 0;
+            let mut num_share_payments: u32 = 0;
+            for (id, source) in batch {
+                // Every recipient of a split stream is paid separately
+                num_recipients = num_recipients.saturating_add(Self::num_recipients(id));
+                let outcome = Self::try_mutate_stream(&source, id, |stream| {
+                    let share = matches!(stream.rate, StreamRate::Share { .. });
+                    let outcome = Self::settle(&source, stream, now, None);
+                    let closed = outcome.is_closed();
+                    Ok::<_, DispatchError>(((outcome, share), closed))
+                });
+                match outcome {
+                    Ok((PaymentOutcome::Exhausted, _)) => num_exhausted_streams += 1,
+                    Ok((_, true)) => num_share_payments += 1,
+                    _ => num_transfers += 1,
+                }
+            }
+            <T as Config>::WeightInfo::on_initialize_stream_exhausted(num_exhausted_streams)
+                .saturating_add(<T as Config>::WeightInfo::on_initialize_transfer(
+                    num_transfers,
+                    num_recipients,
+                ))
+                .saturating_add(<T as Config>::WeightInfo::on_initialize_share_payment(
+                    num_share_payments,
+                ))
+        }
+
+        /// The number of recipients of stream `id` besides its target.
+        fn num_recipients(id: StreamId) -> u32 {
+            <StreamShares<T>>::decode_len(id).unwrap_or(0) as u32
+        }
+
+        /// Mutate stream `id` of `source` with `f`. The stream is removed from storage if `f`
+        /// returns `true` along with its result.
+        fn try_mutate_stream<R, E: From<Error<T>>>(
+            source: &T::AccountId,
+            id: StreamId,
+            f: impl FnOnce(&mut StreamOf<T>) -> Result<(R, bool), E>,
+        ) -> Result<R, E> {
+            <Streams<T>>::try_mutate_exists(source, |maybe_streams| {
+                let streams = maybe_streams.as_mut().ok_or(Error::<T>::StreamNotFound)?;
+                let index = streams
+                    .iter()
+                    .position(|stream| stream.id == id)
+                    .ok_or(Error::<T>::StreamNotFound)?;
+                let stream = streams.get_mut(index).ok_or(Error::<T>::StreamNotFound)?;
+                let (result, remove) = f(stream)?;
+                if remove {
+                    let shares = Self::stream_shares(id).unwrap_or_default();
+                    for payee in Self::payees(&stream.target, &shares) {
+                        Self::remove_incoming(&payee, id);
+                    }
+                    streams.remove(index);
+                    <StreamSources<T>>::remove(id);
+                    <StreamShares<T>>::remove(id);
+                    <SourceTransfers<T>>::remove(id);
+                    // If there are no more streams we can delete the entry
+                    if streams.is_empty() {
+                        *maybe_streams = None;
+                    }
+                }
+                Ok(result)
+            })
+        }
+
+        /// Open a new stream from `source`, split between `target` and the recipients of
+        /// `shares`.
+        fn do_open_stream(
+            source: T::AccountId,
+            target: T::AccountId,
+            asset: StreamAssetOf<T>,
+            spend_rate: BalanceOf<T>,
+            rate: StreamRateOf<T>,
+            shares: Vec<(T::AccountId, Perbill)>,
+            options: StreamOptionsOf<T>,
+        ) -> DispatchResult {
+            // The stream is stored before its funds are set aside, so nothing may be kept if
+            // reserving or escrowing them fails
+            with_transaction(|| {
+                match Self::try_open_stream(
+                    source, target, asset, spend_rate, rate, shares, options,
+                ) {
+                    Ok(()) => TransactionOutcome::Commit(Ok(())),
+                    Err(e) => TransactionOutcome::Rollback(Err(e)),
+                }
+            })
+        }
+
+        fn try_open_stream(
+            source: T::AccountId,
+            target: T::AccountId,
+            asset: StreamAssetOf<T>,
+            spend_rate: BalanceOf<T>,
+            rate: StreamRateOf<T>,
+            shares: Vec<(T::AccountId, Perbill)>,
+            options: StreamOptionsOf<T>,
+        ) -> DispatchResult {
+            let StreamOptions {
+                max_amount,
+                ends_at,
+                cliff,
+                period,
+                grace,
+                escrow,
+            } = options;
 
             if source == target {
                 return Err(Error::<T>::ReflexiveStream.into());
             }
 
-            if T::Currency::free_balance(&source) < spend_rate + T::StreamDeposit::get() {
+            let now = <frame_system::Pallet<T>>::block_number();
+            let state =
+                Self::initial_state(&target, &source, now).ok_or(Error::<T>::StreamNotAccepted)?;
+            let id = Self::next_stream_id();
+            Self::ensure_incoming_capacity(&target, id)?;
+
+            let shares =
+                SharesOf::<T>::try_from(shares).map_err(|_| Error::<T>::TooManyRecipients)?;
+            let total_shares: u64 = shares
+                .iter()
+                .map(|(_, share)| u64::from(share.deconstruct()))
+                .sum();
+            if total_shares > u64::from(Perbill::one().deconstruct()) {
+                return Err(Error::<T>::InvalidShares.into());
+            }
+            for (recipient, _) in shares.iter() {
+                if *recipient == source {
+                    return Err(Error::<T>::ReflexiveStream.into());
+                }
+                if Self::initial_state(recipient, &source, now) != Some(StreamState::Active) {
+                    return Err(Error::<T>::StreamNotAccepted.into());
+                }
+                Self::ensure_incoming_capacity(recipient, id)?;
+            }
+
+            if ends_at.map_or(false, |ends_at| ends_at <= now) {
+                return Err(Error::<T>::InvalidEndBlock.into());
+            }
+
+            if cliff.map_or(false, |cliff| {
+                cliff <= now || ends_at.map_or(false, |ends_at| cliff > ends_at)
+            }) {
+                return Err(Error::<T>::InvalidCliffBlock.into());
+            }
+
+            if period.is_zero() {
+                return Err(Error::<T>::InvalidPeriod.into());
+            }
+
+            if max_amount.is_some()
+                && (asset != StreamAsset::Native || rate != StreamRate::Fixed || escrow.is_some())
+            {
+                return Err(Error::<T>::UnsupportedCap.into());
+            }
+
+            // Capped and escrowed streams need their funds up front, others only the first payment
+            let required = match (rate, escrow) {
+                (_, Some(escrow)) => escrow,
+                (StreamRate::Fixed, None) => max_amount.unwrap_or(spend_rate),
+                (StreamRate::Share { floor, .. }, None) => floor.saturating_add(One::one()),
+            };
+            let deposit = T::StreamDeposit::get();
+            let sufficient = match asset {
+                StreamAsset::Native => {
+                    T::Currency::free_balance(&source) >= required.saturating_add(deposit)
+                }
+                StreamAsset::Asset(_) => {
+                    T::Currency::free_balance(&source) >= deposit
+                        && Self::free_balance(asset, &source) >= required
+                }
+            };
+            if !sufficient {
                 return Err(Error::<T>::InsufficientBalance.into());
             }
 
             <Streams<T>>::try_mutate(&source, |streams| {
                 streams.try_push(Stream {
+                    id,
                     target: target.clone(),
+                    asset,
                     spend_rate,
+                    rate,
+                    pending_rate: None,
+                    remaining: max_amount,
+                    started_at: now,
+                    ends_at,
+                    cliff,
+                    accrued: (0, Zero::zero()),
+                    total_paid: Zero::zero(),
+                    payments_made: 0,
+                    period,
+                    grace,
+                    escrowed: escrow.is_some(),
+                    missed: 0,
+                    last_settled: now,
+                    state,
                 })
             })
             .map_err(|_| Error::<T>::StreamLimitReached)?;
-            T::Currency::reserve(&source, T::StreamDeposit::get())?;
-            Self::deposit_event(Event::StreamOpened(source, target, spend_rate));
+            for payee in Self::payees(&target, &shares) {
+                Self::add_incoming(&payee, &source, id)?;
+            }
+            T::Currency::reserve(
+                &source,
+                T::StreamDeposit::get().saturating_add(max_amount.unwrap_or_default()),
+            )?;
+            if let Some(escrow) = escrow {
+                // The escrow account is kept alive by the pallet, as assets which are not
+                // sufficient cannot be held by an account without a provider
+                let escrow_account = Self::escrow_account(id);
+                frame_system::Pallet::<T>::inc_providers(&escrow_account);
+                Self::transfer(asset, &source, &escrow_account, escrow)?;
+            }
+            <StreamSources<T>>::insert(id, &source);
+            if !shares.is_empty() {
+                <StreamShares<T>>::insert(id, shares);
+            }
+            <NextStreamId<T>>::put(id.saturating_add(1));
+            match state {
+                StreamState::Pending { expires_at } => Self::deposit_event(Event::StreamProposed(
+                    id, source, target, asset, spend_rate, expires_at,
+                )),
+                _ => {
+                    Self::deposit_event(Event::StreamOpened(id, source, target, asset, spend_rate))
+                }
+            }
             Ok(())
         }
 
-        /// Close a stream. From the next block on, transfers will stop.
-        ///
-        /// Index of the stream is counted per-origin, starting with 0. Streams are ordered by
-        /// creation time. Stream's index could change if another stream, with a lower index
-        /// is closed. Streams lookup table should be queried before the call to check the
-        /// appropriate index.
+        /// The state of a new stream from `source` to `target` opened in block `now`, or `None`
+        /// if `target` does not accept streams from `source`.
+        fn initial_state(
+            target: &T::AccountId,
+            source: &T::AccountId,
+            now: T::BlockNumber,
+        ) -> Option<StreamState<T::BlockNumber>> {
+            match Self::incoming_policy(target) {
+                IncomingPolicy::AcceptAll => Some(StreamState::Active),
+                IncomingPolicy::RejectAll => None,
+                IncomingPolicy::AllowList => {
+                    Self::allowed_source(target, source).map(|_| StreamState::Active)
+                }
+                IncomingPolicy::RequireAcceptance => Some(StreamState::Pending {
+                    expires_at: now.saturating_add(T::ProposalLifetime::get()),
+                }),
+            }
+        }
+
+        /// Ensure that `account` can be paid by stream `id`, i.e. it is already paid by the
+        /// stream or can receive another one.
+        fn ensure_incoming_capacity(account: &T::AccountId, id: StreamId) -> DispatchResult {
+            let incoming = Self::incoming_streams(account);
+            ensure!(
+                incoming.iter().any(|(_, i)| *i == id)
+                    || (incoming.len() as u32) < T::MaxIncomingStreams::get(),
+                Error::<T>::IncomingStreamLimitReached
+            );
+            Ok(())
+        }
+
+        /// The accounts paid by a stream to `target` split according to `shares`, each listed
+        /// once.
+        fn payees(target: &T::AccountId, shares: &[(T::AccountId, Perbill)]) -> Vec<T::AccountId> {
+            let mut payees = Vec::with_capacity(shares.len() + 1);
+            payees.push(target.clone());
+            for (recipient, _) in shares {
+                if !payees.contains(recipient) {
+                    payees.push(recipient.clone());
+                }
+            }
+            payees
+        }
+
+        /// Remove the share of `recipient` from stream `id`, along with its incoming stream entry.
+        fn remove_share(id: StreamId, recipient: &T::AccountId) -> DispatchResult {
+            <StreamShares<T>>::try_mutate_exists(id, |maybe_shares| {
+                let shares = maybe_shares.as_mut().ok_or(Error::<T>::NotStreamTarget)?;
+                let len = shares.len();
+                shares.retain(|(account, _)| account != recipient);
+                ensure!(shares.len() < len, Error::<T>::NotStreamTarget);
+                // If there are no more recipients we can delete the entry
+                if shares.is_empty() {
+                    *maybe_shares = None;
+                }
+                Ok::<_, DispatchError>(())
+            })?;
+            Self::remove_incoming(recipient, id);
+            Ok(())
+        }
+
+        /// Whether stream `id` pays a share to `account` besides its target.
+        fn is_recipient(id: StreamId, account: &T::AccountId) -> bool {
+            Self::stream_shares(id).map_or(false, |shares| {
+                shares.iter().any(|(recipient, _)| recipient == account)
+            })
+        }
+
+        /// Add stream `id` of `source` to the incoming streams of `account`, unless it is
+        /// already there.
+        fn add_incoming(
+            account: &T::AccountId,
+            source: &T::AccountId,
+            id: StreamId,
+        ) -> DispatchResult {
+            <IncomingStreams<T>>::try_mutate(account, |incoming| {
+                if incoming.iter().any(|(_, i)| *i == id) {
+                    return Ok(());
+                }
+                incoming.try_push((source.clone(), id))
+            })
+            .map_err(|_| Error::<T>::IncomingStreamLimitReached.into())
+        }
+
+        /// Remove stream `id` from the incoming streams of `target`.
+        fn remove_incoming(target: &T::AccountId, id: StreamId) {
+            <IncomingStreams<T>>::mutate_exists(target, |maybe_incoming| {
+                if let Some(incoming) = maybe_incoming {
+                    incoming.retain(|(_, i)| *i != id);
+                    // If there are no more streams we can delete the entry
+                    if incoming.is_empty() {
+                        *maybe_incoming = None;
+                    }
+                }
+            });
+        }
+
+        /// Settle stream `id` on behalf of its target `who` and assign it to `new_target`.
+        pub(crate) fn do_transfer_target(
+            who: T::AccountId,
+            id: StreamId,
+            new_target: T::AccountId,
+        ) -> DispatchResult {
+            let source = Self::stream_source(id).ok_or(Error::<T>::StreamNotFound)?;
+            let now = <frame_system::Pallet<T>>::block_number();
+            ensure!(source != new_target, Error::<T>::ReflexiveStream);
+            ensure!(
+                Self::initial_state(&new_target, &source, now) == Some(StreamState::Active),
+                Error::<T>::StreamNotAccepted
+            );
+            Self::ensure_incoming_capacity(&new_target, id)?;
+
+            Self::try_mutate_stream(&source, id, |stream| {
+                ensure!(stream.target == who, Error::<T>::NotStreamTarget);
+                // The stream could be closed while being settled
+                if Self::settle(&source, stream, now, None).is_closed() {
+                    return Ok::<_, DispatchError>(((), true));
+                }
+                // The old target stays indexed if it also receives a share of the stream
+                if !Self::is_recipient(id, &who) {
+                    Self::remove_incoming(&who, id);
+                }
+                Self::add_incoming(&new_target, &source, id)?;
+                stream.target = new_target.clone();
+                Self::deposit_event(Event::StreamTargetTransferred(
+                    id,
+                    source.clone(),
+                    who,
+                    new_target,
+                ));
+                Ok(((), false))
+            })
+        }
+
+        /// Settle stream `id` of `source` and move it to `new_source`, together with its funds,
+        /// as long as it pays `spend_rate` and its funds do not exceed `max_funds`.
+        fn do_transfer_source(
+            source: T::AccountId,
+            new_source: T::AccountId,
+            id: StreamId,
+            spend_rate: BalanceOf<T>,
+            max_funds: BalanceOf<T>,
+            now: T::BlockNumber,
+        ) -> DispatchResult {
+            // The stream could be closed while being settled
+            let closed = Self::try_mutate_stream(&source, id, |stream| {
+                let closed = Self::settle(&source, stream, now, None).is_closed();
+                Ok::<_, DispatchError>((closed, closed))
+            })?;
+            if closed {
+                return Ok(());
+            }
+
+            let stream = <Streams<T>>::try_mutate_exists(&source, |maybe_streams| {
+                let streams = maybe_streams.as_mut().ok_or(Error::<T>::StreamNotFound)?;
+                let index = streams
+                    .iter()
+                    .position(|stream| stream.id == id)
+                    .ok_or(Error::<T>::StreamNotFound)?;
+                let stream = streams.remove(index);
+                if streams.is_empty() {
+                    *maybe_streams = None;
+                }
+                Ok::<_, DispatchError>(stream)
+            })?;
+            // The stream could have been assigned to the new source since the proposal
+            ensure!(
+                stream.target != new_source
+                    && !Self::stream_shares(id).map_or(false, |shares| {
+                        shares.iter().any(|(recipient, _)| *recipient == new_source)
+                    }),
+                Error::<T>::ReflexiveStream
+            );
+            // The stream could have been changed or topped up since the proposal
+            let escrow = if stream.escrowed {
+                Self::escrow_balance(&stream)
+            } else {
+                Zero::zero()
+            };
+            ensure!(
+                stream.spend_rate == spend_rate
+                    && stream
+                        .pending_rate
+                        .map_or(true, |(_, pending_rate)| pending_rate == spend_rate)
+                    && stream.remaining.unwrap_or_default().saturating_add(escrow) <= max_funds,
+                Error::<T>::SourceTransferTermsChanged
+            );
+            // The recipients must accept the new source just like for a new stream
+            let accepted = |recipient: &T::AccountId| {
+                Self::initial_state(recipient, &new_source, now) == Some(StreamState::Active)
+            };
+            ensure!(
+                accepted(&stream.target)
+                    && Self::stream_shares(id).map_or(true, |shares| {
+                        shares.iter().all(|(recipient, _)| accepted(recipient))
+                    }),
+                Error::<T>::StreamNotAccepted
+            );
+            let funds =
+                T::StreamDeposit::get().saturating_add(stream.remaining.unwrap_or_default());
+            T::Currency::unreserve(&source, funds);
+            T::Currency::reserve(&new_source, funds)
+                .map_err(|_| Error::<T>::InsufficientBalance)?;
+            if stream.escrowed {
+                // The escrow stays in place, so the new source buys it from the old one
+                Self::transfer(stream.asset, &new_source, &source, escrow)
+                    .map_err(|_| Error::<T>::InsufficientBalance)?;
+            }
+            let shares = Self::stream_shares(id).unwrap_or_default();
+            let payees = Self::payees(&stream.target, &shares);
+            <Streams<T>>::try_mutate(&new_source, |streams| streams.try_push(stream))
+                .map_err(|_| Error::<T>::StreamLimitReached)?;
+            <StreamSources<T>>::insert(id, &new_source);
+            for payee in payees {
+                <IncomingStreams<T>>::mutate(&payee, |incoming| {
+                    for entry in incoming.iter_mut().filter(|(_, i)| *i == id) {
+                        entry.0 = new_source.clone();
+                    }
+                });
+            }
+            <SourceTransfers<T>>::remove(id);
+            Self::deposit_event(Event::StreamSourceTransferred(id, source, new_source));
+            Ok(())
+        }
+
+        /// Settle stream `id` on behalf of its target `who`, transferring the payments to
+        /// `beneficiary`.
+        fn do_claim(who: T::AccountId, id: StreamId, beneficiary: T::AccountId) -> DispatchResult {
+            let source = Self::stream_source(id).ok_or(Error::<T>::StreamNotFound)?;
+            let now = <frame_system::Pallet<T>>::block_number();
+            Self::try_mutate_stream(&source, id, |stream| {
+                ensure!(stream.target == who, Error::<T>::NotStreamTarget);
+                let from = stream.last_settled;
+                let paid_before = stream.total_paid;
+                let outcome = Self::settle(&source, stream, now, Some(&beneficiary));
+                Self::deposit_event(Event::Claimed(
+                    id,
+                    who,
+                    beneficiary,
+                    stream.total_paid.saturating_sub(paid_before),
+                    from,
+                    stream.last_settled,
+                ));
+                Ok::<_, DispatchError>(((), outcome.is_closed()))
+            })
+        }
+
+        /// The number of payments of `stream` which have become due after it was last settled,
+        /// up to block `until`. Payments become due every `period` blocks, starting with the
+        /// block following the opening one.
+        fn payments_due(stream: &StreamOf<T>, until: T::BlockNumber) -> u32 {
+            let payments_until =
+                |block: T::BlockNumber| block.saturating_sub(stream.started_at) / stream.period;
+            payments_until(until)
+                .saturating_sub(payments_until(stream.last_settled))
+                .unique_saturated_into()
+        }
+
+        /// Settle `stream` of `source` in block `now`, making all payments which have become
+        /// due since it was last settled. The payments are transferred to `beneficiary` if given
+        /// or to the target of the stream otherwise. Streams which should not continue are
+        /// closed and their funds are released.
+        fn settle(
+            source: &T::AccountId,
+            stream: &mut StreamOf<T>,
+            now: T::BlockNumber,
+            beneficiary: Option<&T::AccountId>,
+        ) -> PaymentOutcome {
+            if let StreamState::Pending { expires_at } = stream.state {
+                if now < expires_at {
+                    return PaymentOutcome::NotDue;
+                }
+                Self::release_funds(source, stream);
+                Self::deposit_event(Event::StreamProposalExpired(
+                    stream.id,
+                    source.clone(),
+                    stream.target.clone(),
+                ));
+                return PaymentOutcome::Expired;
+            }
+
+            let dest = beneficiary.unwrap_or(&stream.target).clone();
+            if let StreamState::Paused {
+                resume_at: Some(resume_at),
+            } = stream.state
+            {
+                if resume_at <= now {
+                    Self::resume(source, stream, resume_at);
+                }
+            }
+
+            // Payments due before a scheduled rate change are made at the old rate
+            if let Some((effective_at, spend_rate)) = stream.pending_rate {
+                if effective_at <= now {
+                    let until = effective_at.saturating_sub(One::one());
+                    if Self::before_cliff(stream, until) {
+                        Self::accrue(stream, until);
+                    } else {
+                        let outcome = Self::pay_due(source, stream, &dest, until);
+                        if outcome.is_closed() {
+                            return outcome;
+                        }
+                    }
+                    stream.spend_rate = spend_rate;
+                    stream.pending_rate = None;
+                }
+            }
+
+            match Self::pay_due(source, stream, &dest, now) {
+                PaymentOutcome::NotDue | PaymentOutcome::Continued
+                    if stream.ends_at.map_or(false, |end| now >= end) =>
+                {
+                    Self::release_funds(source, stream);
+                    Self::deposit_event(Event::StreamCompleted(
+                        stream.id,
+                        source.clone(),
+                        stream.target.clone(),
+                        stream.spend_rate,
+                        stream.total_paid,
+                        stream.payments_made,
+                    ));
+                    PaymentOutcome::Completed
+                }
+                outcome => outcome,
+            }
+        }
+
+        /// Make the payments of `stream` to `dest` which have become due after it was last
+        /// settled, up to block `until`.
+        fn pay_due(
+            source: &T::AccountId,
+            stream: &mut StreamOf<T>,
+            dest: &T::AccountId,
+            until: T::BlockNumber,
+        ) -> PaymentOutcome {
+            let until = stream.ends_at.map_or(until, |end| end.min(until));
+            // Payments accrue until the cliff and are all made at once in the cliff block
+            if Self::before_cliff(stream, until) {
+                return PaymentOutcome::NotDue;
+            }
+            let due = Self::active_payments_due(stream, until);
+            stream.last_settled = stream.last_settled.max(until);
+            match Self::pay_accrued(source, stream, dest) {
+                outcome if outcome.is_closed() || due == 0 => outcome,
+                _ => Self::make_payments(source, stream, dest, due),
+            }
+        }
+
+        /// The number of payments of `stream` which have become due up to block `until`, which
+        /// is zero while the stream is not active.
+        fn active_payments_due(stream: &StreamOf<T>, until: T::BlockNumber) -> u32 {
+            // No payments become due while the stream is paused
+            match stream.state {
+                StreamState::Active => Self::payments_due(stream, until),
+                StreamState::Paused { .. } | StreamState::Pending { .. } => 0,
+            }
+        }
+
+        /// Whether block `at` lies before the cliff of `stream`.
+        fn before_cliff(stream: &StreamOf<T>, at: T::BlockNumber) -> bool {
+            stream.cliff.map_or(false, |cliff| at < cliff)
+        }
+
+        /// Record the payments of `stream` which have become due up to block `until` before its
+        /// cliff at the current spend rate, so that they are made at that rate in the cliff
+        /// block even if the rate changes meanwhile.
+        fn accrue(stream: &mut StreamOf<T>, until: T::BlockNumber) {
+            let due = Self::active_payments_due(stream, until);
+            let (count, amount) = stream.accrued;
+            stream.accrued = (
+                count.saturating_add(due),
+                amount.saturating_add(stream.spend_rate.saturating_mul(due.into())),
+            );
+            stream.last_settled = stream.last_settled.max(until);
+        }
+
+        /// Make the payments of `stream` to `dest` accrued before its cliff at once. Capped
+        /// streams pay at most their remaining cap.
+        fn pay_accrued(
+            source: &T::AccountId,
+            stream: &mut StreamOf<T>,
+            dest: &T::AccountId,
+        ) -> PaymentOutcome {
+            let (count, amount) = stream.accrued;
+            if count == 0 {
+                return PaymentOutcome::NotDue;
+            }
+            stream.accrued = (0, Zero::zero());
+            let amount = stream
+                .remaining
+                .map_or(amount, |remaining| amount.min(remaining));
+            match Self::pay(source, stream, dest, amount) {
+                Ok(paid) => {
+                    stream.remaining = stream
+                        .remaining
+                        .map(|remaining| remaining.saturating_sub(paid));
+                    Self::record_payments(stream, count, paid);
+                    PaymentOutcome::Continued
+                }
+                Err(e) => Self::miss_payments(source, stream, count, amount, e),
+            }
+        }
+
+        /// Resume a paused `stream` of `source` after block `at`. Payments for the blocks up to
+        /// `at` are skipped.
+        fn resume(source: &T::AccountId, stream: &mut StreamOf<T>, at: T::BlockNumber) {
+            stream.last_settled = stream.last_settled.max(at);
+            stream.state = StreamState::Active;
+            Self::deposit_event(Event::StreamResumed(
+                stream.id,
+                source.clone(),
+                stream.target.clone(),
+            ));
+        }
+
+        /// Make `due` payments of `stream` to `dest` at once.
+        fn make_payments(
+            source: &T::AccountId,
+            stream: &mut StreamOf<T>,
+            dest: &T::AccountId,
+            due: u32,
+        ) -> PaymentOutcome {
+            if let StreamRate::Share { share, floor } = stream.rate {
+                return Self::make_share_payments(source, stream, dest, due, share, floor);
+            }
+            let remaining = match stream.remaining {
+                Some(remaining) => remaining,
+                None => return Self::make_free_payments(source, stream, dest, due),
+            };
+
+            // Capped streams are paid from the funds reserved when the stream was opened
+            let amount = stream.spend_rate.saturating_mul(due.into()).min(remaining);
+            let paid = match Self::pay(source, stream, dest, amount) {
+                Ok(paid) => paid,
+                Err(e) => return Self::miss_payments(source, stream, due, amount, e),
+            };
+            let unpaid = amount.saturating_sub(paid);
+            stream.remaining = Some(remaining.saturating_sub(paid));
+            if !paid.is_zero() {
+                Self::record_payments(stream, due, paid);
+            }
+
+            if !unpaid.is_zero() {
+                // The reserve no longer covers the cap, e.g. because it has been slashed
+                Self::release_funds(source, stream);
+                Self::deposit_event(Event::StreamExhausted(
+                    stream.id,
+                    source.clone(),
+                    stream.target.clone(),
+                    unpaid,
+                    stream.total_paid,
+                    stream.payments_made,
+                ));
+                PaymentOutcome::Exhausted
+            } else if remaining == paid {
+                Self::release_funds(source, stream);
+                Self::deposit_event(Event::StreamCompleted(
+                    stream.id,
+                    source.clone(),
+                    stream.target.clone(),
+                    stream.spend_rate,
+                    stream.total_paid,
+                    stream.payments_made,
+                ));
+                PaymentOutcome::Completed
+            } else {
+                PaymentOutcome::Continued
+            }
+        }
+
+        /// Make `due` payments of an uncapped stream to `dest` from the free balance of the
+        /// source. Only as many payments as the source can afford are made, the rest are missed.
+        fn make_free_payments(
+            source: &T::AccountId,
+            stream: &mut StreamOf<T>,
+            dest: &T::AccountId,
+            due: u32,
+        ) -> PaymentOutcome {
+            let spend_rate = stream.spend_rate;
+            let affordable: u32 = if spend_rate.is_zero() {
+                due
+            } else {
+                let balance = Self::free_balance(stream.asset, &Self::payer(source, stream));
+                (balance / spend_rate).unique_saturated_into()
+            };
+            let paid = due.min(affordable);
+            if paid > 0 {
+                let amount = spend_rate.saturating_mul(paid.into());
+                match Self::pay(source, stream, dest, amount) {
+                    Ok(_) => Self::record_payments(stream, paid, amount),
+                    Err(e) => return Self::miss_payments(source, stream, due, amount, e),
+                }
+            }
+
+            let missed = due.saturating_sub(paid);
+            if missed == 0 {
+                PaymentOutcome::Continued
+            } else {
+                Self::miss_payments(
+                    source,
+                    stream,
+                    missed,
+                    spend_rate.saturating_mul(missed.into()),
+                    Error::<T>::InsufficientBalance.into(),
+                )
+            }
+        }
+
+        /// Make `due` payments of a stream paying `share` of the free balance of the source to
+        /// `dest` at once. Every payment leaves at least `floor` to the source and the stream is
+        /// completed once the balance of the source reaches `floor`.
+        fn make_share_payments(
+            source: &T::AccountId,
+            stream: &mut StreamOf<T>,
+            dest: &T::AccountId,
+            due: u32,
+            share: Perbill,
+            floor: BalanceOf<T>,
+        ) -> PaymentOutcome {
+            // After `due` payments `(1 - share)^due` of the balance is left
+            let balance = Self::free_balance(stream.asset, &Self::payer(source, stream));
+            let kept = Perbill::one()
+                .saturating_sub(share)
+                .saturating_pow(due as usize)
+                .mul_ceil(balance);
+            let amount = balance.saturating_sub(kept.max(floor));
+            if !amount.is_zero() {
+                match Self::pay(source, stream, dest, amount) {
+                    Ok(paid) => Self::record_payments(stream, due, paid),
+                    Err(e) => return Self::miss_payments(source, stream, due, amount, e),
+                }
+            }
+
+            if kept <= floor {
+                Self::release_funds(source, stream);
+                Self::deposit_event(Event::StreamCompleted(
+                    stream.id,
+                    source.clone(),
+                    stream.target.clone(),
+                    stream.spend_rate,
+                    stream.total_paid,
+                    stream.payments_made,
+                ));
+                PaymentOutcome::Completed
+            } else {
+                PaymentOutcome::Continued
+            }
+        }
+
+        /// Pay `amount` owed by `stream` of `source`, splitting it between the recipients of the
+        /// stream according to their shares. `dest` receives the rest, including the rounding
+        /// dust. Capped streams are paid from the reserve, which may cover only a part of
+        /// `amount`, and others from the free balance of the source.
         ///
-        /// The dispatch origin for this call must be _Signed_.
-        #[pallet::weight(<T as Config>::WeightInfo::close_stream(0))]
-        pub fn close_stream(origin: OriginFor<T>, index: u32) -> DispatchResult {
-            let source = ensure_signed(origin)?;
-            let index = index as usize;
-            let Stream { target, spend_rate } = <Streams<T>>::try_mutate(&source, |streams| {
-                if index < streams.len() {
-                    Ok(streams.remove(index))
-                } else {
-                    Err(Error::<T>::StreamNotFound)
+        /// Either all recipients are paid or none of them, in which case an error is returned.
+        /// Otherwise the total amount paid is returned.
+        fn pay(
+            source: &T::AccountId,
+            stream: &StreamOf<T>,
+            dest: &T::AccountId,
+            amount: BalanceOf<T>,
+        ) -> Result<BalanceOf<T>, DispatchError> {
+            let mut rest = amount;
+            let mut parts: Vec<_> = Self::stream_shares(stream.id)
+                .map(|shares| shares.into_inner())
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(recipient, share)| {
+                    let part = share.mul_floor(amount);
+                    rest = rest.saturating_sub(part);
+                    (recipient, part)
+                })
+                .collect();
+            parts.push((dest.clone(), rest));
+
+            let payer = Self::payer(source, stream);
+            let paid = with_transaction(|| {
+                let mut paid = Vec::with_capacity(parts.len());
+                for (recipient, part) in parts {
+                    let result = if stream.remaining.is_some() {
+                        T::Currency::repatriate_reserved(
+                            source,
+                            &recipient,
+                            part,
+                            BalanceStatus::Free,
+                        )
+                        .map(|unpaid| part.saturating_sub(unpaid))
+                    } else {
+                        Self::transfer(stream.asset, &payer, &recipient, part).map(|_| part)
+                    };
+                    match result {
+                        Ok(part) => paid.push((recipient, part)),
+                        Err(e) => return TransactionOutcome::Rollback(Err(e)),
+                    }
                 }
+                TransactionOutcome::Commit(Ok(paid))
             })?;
-            T::Currency::unreserve(&source, T::StreamDeposit::get());
-            Self::deposit_event(Event::StreamClosed(source, target, spend_rate));
+
+            let mut total = Zero::zero();
+            for (recipient, part) in paid {
+                if part.is_zero() {
+                    continue;
+                }
+                total = part.saturating_add(total);
+                Self::deposit_event(Event::PaymentMade(
+                    stream.id,
+                    source.clone(),
+                    recipient,
+                    part,
+                ));
+            }
+            Ok(total)
+        }
+
+        /// Record `count` payments of total `amount` which could not be made. The stream is
+        /// closed once it has missed more than `grace` consecutive payments.
+        fn miss_payments(
+            source: &T::AccountId,
+            stream: &mut StreamOf<T>,
+            count: u32,
+            amount: BalanceOf<T>,
+            reason: DispatchError,
+        ) -> PaymentOutcome {
+            stream.missed = stream.missed.saturating_add(count);
+            if stream.missed > stream.grace {
+                Self::deposit_event(Event::StreamExhausted(
+                    stream.id,
+                    source.clone(),
+                    stream.target.clone(),
+                    amount,
+                    stream.total_paid,
+                    stream.payments_made,
+                ));
+                // Return deposit and remove the exhausted stream
+                Self::release_funds(source, stream);
+                PaymentOutcome::Exhausted
+            } else {
+                Self::deposit_event(Event::PaymentFailed(
+                    stream.id,
+                    source.clone(),
+                    stream.target.clone(),
+                    amount,
+                    reason,
+                ));
+                PaymentOutcome::Continued
+            }
+        }
+
+        /// Update the statistics of `stream` after it has successfully made `count` payments of
+        /// total `amount`.
+        fn record_payments(stream: &mut StreamOf<T>, count: u32, amount: BalanceOf<T>) {
+            stream.total_paid = stream.total_paid.saturating_add(amount);
+            stream.payments_made = stream.payments_made.saturating_add(count);
+            stream.missed = 0;
+        }
+
+        /// The balance of `asset` which `who` can spend.
+        fn free_balance(asset: StreamAssetOf<T>, who: &T::AccountId) -> BalanceOf<T> {
+            match asset {
+                StreamAsset::Native => T::Currency::free_balance(who),
+                StreamAsset::Asset(id) => T::Assets::reducible_balance(id, who, false),
+            }
+        }
+
+        /// Transfer `amount` of `asset` from `source` to `dest`, allowing `source` to be reaped.
+        fn transfer(
+            asset: StreamAssetOf<T>,
+            source: &T::AccountId,
+            dest: &T::AccountId,
+            amount: BalanceOf<T>,
+        ) -> DispatchResult {
+            match asset {
+                StreamAsset::Native => T::Currency::transfer(source, dest, amount, AllowDeath),
+                StreamAsset::Asset(id) => {
+                    T::Assets::transfer(id, source, dest, amount, false).map(|_| ())
+                }
+            }
+        }
+
+        /// Unreserve the deposit of a closed stream, together with the unspent part of its cap.
+        fn release_funds(source: &T::AccountId, stream: &StreamOf<T>) {
+            T::Currency::unreserve(
+                source,
+                T::StreamDeposit::get().saturating_add(stream.remaining.unwrap_or_default()),
+            );
+            if stream.escrowed {
+                let escrow = Self::escrow_account(stream.id);
+                let amount = Self::escrow_balance(stream);
+                // The refund fails if the source cannot receive it, e.g. because the asset is
+                // frozen, in which case the funds stay in the escrow account along with its
+                // provider
+                match Self::transfer(stream.asset, &escrow, source, amount) {
+                    Ok(()) => {
+                        // Other assets sent to the escrow account keep it alive
+                        if let Err(e) = frame_system::Pallet::<T>::dec_providers(&escrow) {
+                            log::warn!(
+                                target: "runtime::stream-payments",
+                                "escrow account {:?} of stream {} kept alive: {:?}",
+                                escrow,
+                                stream.id,
+                                e,
+                            );
+                        }
+                    }
+                    Err(e) => Self::deposit_event(Event::EscrowRefundFailed(
+                        stream.id,
+                        source.clone(),
+                        amount,
+                        e,
+                    )),
+                }
+            }
+        }
+
+        /// The account from which `stream` of `source` is paid.
+        fn payer(source: &T::AccountId, stream: &StreamOf<T>) -> T::AccountId {
+            if stream.escrowed {
+                Self::escrow_account(stream.id)
+            } else {
+                source.clone()
+            }
+        }
+
+        /// The extra weight of opening a stream with `options` for funding its escrow, if any.
+        fn escrow_weight(options: &StreamOptionsOf<T>) -> Weight {
+            match options.escrow {
+                Some(_) => T::WeightInfo::open_escrowed_stream()
+                    .saturating_sub(T::WeightInfo::open_stream()),
+                None => 0,
+            }
+        }
+
+        /// The whole balance of the escrow account of `stream`, even if it cannot be spent right
+        /// now.
+        fn escrow_balance(stream: &StreamOf<T>) -> BalanceOf<T> {
+            let escrow = Self::escrow_account(stream.id);
+            match stream.asset {
+                StreamAsset::Native => T::Currency::free_balance(&escrow),
+                StreamAsset::Asset(id) => T::Assets::balance(id, &escrow),
+            }
+        }
+
+        /// The escrow account of stream `id`.
+        pub fn escrow_account(id: StreamId) -> T::AccountId {
+            T::PalletId::get().into_sub_account(id)
+        }
+    }
+
+    // Invariants of the pallet storage.
+    #[cfg(any(feature = "try-runtime", test))]
+    impl<T: Config> Pallet<T> {
+        /// Check the invariants of the open streams:
+        ///
+        /// * The balance reserved by every source covers the deposits of its streams, together
+        ///   with the unspent part of their caps.
+        /// * No source has an empty stream vector or more than `MaxStreams` streams.
+        /// * No stream targets its own source.
+        pub fn do_try_state() -> Result<(), &'static str> {
+            for (source, streams) in <Streams<T>>::iter() {
+                if streams.is_empty() {
+                    return Err("Empty stream vector stored");
+                }
+                if streams.len() > T::MaxStreams::get() as usize {
+                    return Err("Too many streams of a single source");
+                }
+                if streams.iter().any(|stream| stream.target == source) {
+                    return Err("Reflexive stream");
+                }
+                let deposits =
+                    T::StreamDeposit::get().saturating_mul((streams.len() as u32).into());
+                let held = streams.iter().fold(deposits, |held, stream| {
+                    held.saturating_add(stream.remaining.unwrap_or_default())
+                });
+                if T::Currency::reserved_balance(&source) < held {
+                    return Err("Reserved balance does not cover stream deposits");
+                }
+            }
             Ok(())
         }
     }
+
+    // Queries backing the runtime API.
+    impl<T: Config> Pallet<T> {
+        /// All open streams of `source`.
+        pub fn streams_by_source(source: T::AccountId) -> Vec<StreamOf<T>> {
+            Self::streams(source).into_inner()
+        }
+
+        /// All open streams paying `target`, either as their target or as a recipient of their
+        /// shares, along with their sources.
+        pub fn streams_by_target(target: T::AccountId) -> Vec<(T::AccountId, StreamOf<T>)> {
+            Self::incoming_streams(target)
+                .into_iter()
+                .filter_map(|(source, id)| {
+                    let stream = Self::streams(&source)
+                        .iter()
+                        .find(|stream| stream.id == id)
+                        .cloned()?;
+                    Some((source, stream))
+                })
+                .collect()
+        }
+
+        /// The amount of `asset` committed per block by the active streams of `account`,
+        /// averaged over the payment period of every stream.
+        pub fn outflow_per_block(account: T::AccountId, asset: StreamAssetOf<T>) -> BalanceOf<T> {
+            Self::streams(&account)
+                .iter()
+                .filter(|stream| stream.asset == asset && stream.state == StreamState::Active)
+                .map(|stream| Self::rate_per_block(&account, stream))
+                .fold(Zero::zero(), |total: BalanceOf<T>, rate| {
+                    total.saturating_add(rate)
+                })
+        }
+
+        /// The number of blocks for which the current free balance of `account` covers its
+        /// active fixed-rate streams of `asset`. Capped and escrowed streams are not paid from
+        /// the free balance, so they are not taken into account. `None` if no such stream pays
+        /// anything.
+        pub fn runway(account: T::AccountId, asset: StreamAssetOf<T>) -> Option<T::BlockNumber> {
+            let outflow = Self::streams(&account)
+                .iter()
+                .filter(|stream| {
+                    stream.asset == asset
+                        && stream.state == StreamState::Active
+                        && stream.rate == StreamRate::Fixed
+                        && stream.remaining.is_none()
+                        && !stream.escrowed
+                })
+                .map(|stream| Self::rate_per_block(&account, stream))
+                .fold(Zero::zero(), |total: BalanceOf<T>, rate| {
+                    total.saturating_add(rate)
+                });
+            if outflow.is_zero() {
+                return None;
+            }
+            let blocks: u32 =
+                (Self::free_balance(asset, &account) / outflow).unique_saturated_into();
+            Some(blocks.into())
+        }
+
+        /// The amount every open stream would pay if it was settled `blocks` blocks from now,
+        /// given the current balances. Nothing is changed, all payments are rolled back.
+        pub fn projected_payments(blocks: T::BlockNumber) -> Vec<(StreamId, BalanceOf<T>)> {
+            let until = <frame_system::Pallet<T>>::block_number().saturating_add(blocks);
+            with_transaction(|| {
+                let mut payments = Vec::new();
+                for (source, streams) in <Streams<T>>::iter() {
+                    for mut stream in streams.into_inner() {
+                        let paid_before = stream.total_paid;
+                        Self::settle(&source, &mut stream, until, None);
+                        payments.push((stream.id, stream.total_paid.saturating_sub(paid_before)));
+                    }
+                }
+                TransactionOutcome::Rollback(payments)
+            })
+        }
+
+        /// The amount paid by `stream` of `source` per block, averaged over its payment period.
+        fn rate_per_block(source: &T::AccountId, stream: &StreamOf<T>) -> BalanceOf<T> {
+            let payment = match stream.rate {
+                StreamRate::Fixed => stream.spend_rate,
+                StreamRate::Share { share, .. } => share.mul_floor(Self::free_balance(
+                    stream.asset,
+                    &Self::payer(source, stream),
+                )),
+            };
+            let period: u32 = stream.period.unique_saturated_into();
+            payment / period.max(1).into()
+        }
+    }
 }
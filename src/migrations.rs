@@ -0,0 +1,229 @@
+//! Storage migrations for the stream-payments pallet.
+//!
+//! Every migration lives in a module named after the storage version it migrates to and does
+//! nothing once that version has been reached, so [`Migration`] can run all of them in order on
+//! every runtime upgrade. The pallet runs it from its `on_runtime_upgrade` hook.
+//!
+//! With the `try-runtime` feature, the number of streams and the balance reserved by their
+//! sources are recorded before the upgrade and checked to be preserved afterwards, except for
+//! the streams exceeding `MaxStreams`, which cannot be kept.
+
+use super::*;
+use codec::{Decode, Encode};
+use frame_support::sp_runtime::traits::{One, Saturating, Zero};
+#[cfg(feature = "try-runtime")]
+use frame_support::traits::OnRuntimeUpgradeHelpersExt;
+use frame_support::{
+    log,
+    traits::{Get, GetStorageVersion, OnRuntimeUpgrade, ReservableCurrency, StorageVersion},
+    weights::Weight,
+    RuntimeDebug,
+};
+use sp_std::marker::PhantomData;
+
+/// Migrate the storage from any earlier version to the current one.
+pub struct Migration<T>(PhantomData<T>);
+
+impl<T: Config> OnRuntimeUpgrade for Migration<T> {
+    fn on_runtime_upgrade() -> Weight {
+        v1::migrate::<T>()
+    }
+
+    #[cfg(feature = "try-runtime")]
+    fn pre_upgrade() -> Result<(), &'static str> {
+        Self::set_temp_storage(stream_stats::<T>(), "stream_stats");
+        Ok(())
+    }
+
+    #[cfg(feature = "try-runtime")]
+    fn post_upgrade() -> Result<(), &'static str> {
+        let before: StreamStats<BalanceOf<T>> =
+            Self::get_temp_storage("stream_stats").ok_or("No stream stats recorded")?;
+        check_upgrade::<T>(before)
+    }
+}
+
+/// Summary of the open streams which must be preserved by every migration.
+#[derive(Encode, Decode, Eq, PartialEq, RuntimeDebug)]
+pub struct StreamStats<Balance> {
+    /// The number of sources with open streams.
+    pub sources: u32,
+    /// The number of open streams.
+    pub streams: u32,
+    /// The number of open streams exceeding `MaxStreams` for their source.
+    pub excess: u32,
+    /// The total balance reserved by the sources of open streams.
+    pub reserved: Balance,
+}
+
+/// Collect the stats of the open streams. Only the length of the stream vector of every source
+/// is decoded, so this works with any storage version.
+pub fn stream_stats<T: Config>() -> StreamStats<BalanceOf<T>> {
+    let mut stats = StreamStats {
+        sources: 0,
+        streams: 0,
+        excess: 0,
+        reserved: Zero::zero(),
+    };
+    for source in Streams::<T>::iter_keys() {
+        let len = Streams::<T>::decode_len(&source).unwrap_or_default() as u32;
+        stats.sources += 1;
+        stats.streams += len;
+        stats.excess += len.saturating_sub(T::MaxStreams::get());
+        stats.reserved = stats
+            .reserved
+            .saturating_add(T::Currency::reserved_balance(&source));
+    }
+    stats
+}
+
+/// Check that the storage is consistent and matches the stats collected before the upgrade.
+/// Streams exceeding `MaxStreams` must have been closed and their deposits returned.
+pub fn check_upgrade<T: Config>(before: StreamStats<BalanceOf<T>>) -> Result<(), &'static str> {
+    if Pallet::<T>::on_chain_storage_version() != Pallet::<T>::current_storage_version() {
+        return Err("Storage version not updated");
+    }
+    let after = stream_stats::<T>();
+    if after.sources != before.sources || after.streams != before.streams - before.excess {
+        return Err("Streams lost during migration");
+    }
+    let returned = T::StreamDeposit::get().saturating_mul(before.excess.into());
+    if after.reserved != before.reserved.saturating_sub(returned) {
+        return Err("Reserved deposits changed during migration");
+    }
+    let mut max_id = None;
+    for (source, streams) in Streams::<T>::iter() {
+        for stream in streams.iter() {
+            if StreamSources::<T>::get(stream.id).as_ref() != Some(&source) {
+                return Err("Stream source not indexed");
+            }
+            if !IncomingStreams::<T>::get(&stream.target).contains(&(source.clone(), stream.id)) {
+                return Err("Stream target not indexed");
+            }
+            let shares = StreamShares::<T>::get(stream.id).unwrap_or_default();
+            if shares.iter().any(|(recipient, _)| {
+                !IncomingStreams::<T>::get(recipient).contains(&(source.clone(), stream.id))
+            }) {
+                return Err("Stream recipient not indexed");
+            }
+            max_id = max_id.max(Some(stream.id));
+        }
+    }
+    if StreamSources::<T>::iter_keys().count() as u32 != after.streams {
+        return Err("Stream sources of closed streams left behind");
+    }
+    if max_id.map_or(false, |id| id >= NextStreamId::<T>::get()) {
+        return Err("Stream identifier reused");
+    }
+    Ok(())
+}
+
+/// Migration from the initial storage layout, where streams were addressed by their position
+/// in the per-source vector, to stream identifiers.
+pub mod v1 {
+    use super::*;
+
+    /// Stream layout before identifiers were introduced.
+    #[derive(Decode)]
+    struct OldStream<AccountId, Balance> {
+        target: AccountId,
+        spend_rate: Balance,
+    }
+
+    /// Assign a unique identifier to every open stream.
+    ///
+    /// Streams of a single source keep their relative order, so their identifiers are
+    /// increasing in the order of creation. The opening block of existing streams is not known,
+    /// so their statistics and payments are counted from the block of the migration.
+    ///
+    /// Only the first `MaxStreams` streams of every source are kept. The later ones are closed
+    /// and their deposits are returned. Streams whose target already has `MaxIncomingStreams`
+    /// incoming streams are kept but cannot be indexed, which is logged and fails the
+    /// `post_upgrade` check.
+    pub fn migrate<T: Config>() -> Weight {
+        if Pallet::<T>::on_chain_storage_version() >= 1 {
+            return T::DbWeight::get().reads(1);
+        }
+
+        let now = <frame_system::Pallet<T>>::block_number();
+        let max_streams = T::MaxStreams::get() as usize;
+        let mut next_id = NextStreamId::<T>::get();
+        let mut num_sources: Weight = 0;
+        let mut num_streams: Weight = 0;
+        let mut num_truncated: Weight = 0;
+        let mut num_unindexed: u32 = 0;
+        Streams::<T>::translate::<Vec<OldStream<AccountIdOf<T>, BalanceOf<T>>>, _>(
+            |source, mut old_streams| {
+                num_sources += 1;
+                if old_streams.len() > max_streams {
+                    let excess = (old_streams.len() - max_streams) as u32;
+                    old_streams.truncate(max_streams);
+                    T::Currency::unreserve(
+                        &source,
+                        T::StreamDeposit::get().saturating_mul(excess.into()),
+                    );
+                    num_truncated += 1;
+                    log::warn!(
+                        target: "runtime::stream-payments",
+                        "closed {} streams exceeding MaxStreams of source {:?}",
+                        excess,
+                        source,
+                    );
+                }
+                let streams: Vec<_> = old_streams
+                    .into_iter()
+                    .map(|OldStream { target, spend_rate }| {
+                        let id = next_id;
+                        next_id = next_id.saturating_add(1);
+                        num_streams += 1;
+                        StreamSources::<T>::insert(id, &source);
+                        if IncomingStreams::<T>::try_mutate(&target, |incoming| {
+                            incoming.try_push((source.clone(), id))
+                        })
+                        .is_err()
+                        {
+                            num_unindexed += 1;
+                        }
+                        Stream {
+                            id,
+                            target,
+                            asset: StreamAsset::Native,
+                            spend_rate,
+                            rate: StreamRate::Fixed,
+                            pending_rate: None,
+                            remaining: None,
+                            started_at: now,
+                            ends_at: None,
+                            cliff: None,
+                            accrued: (0, Zero::zero()),
+                            total_paid: Zero::zero(),
+                            payments_made: 0,
+                            period: One::one(),
+                            grace: 0,
+                            escrowed: false,
+                            missed: 0,
+                            last_settled: now,
+                            state: StreamState::Active,
+                        }
+                    })
+                    .collect();
+                // Cannot fail as the streams have been truncated to the bound
+                StreamVec::<T>::try_from(streams).ok()
+            },
+        );
+        if num_unindexed > 0 {
+            log::error!(
+                target: "runtime::stream-payments",
+                "{} streams could not be indexed by their target",
+                num_unindexed,
+            );
+        }
+        NextStreamId::<T>::put(next_id);
+        StorageVersion::new(1).put::<Pallet<T>>();
+
+        T::DbWeight::get().reads_writes(
+            num_sources + num_streams + num_truncated + 3,
+            num_sources + 2 * num_streams + num_truncated + 2,
+        )
+    }
+}
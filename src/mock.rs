@@ -1,5 +1,6 @@
 use crate as stream_payments;
-use frame_support::traits::{ConstU16, ConstU64};
+use frame_support::traits::{ConstU16, ConstU32, ConstU64};
+use frame_support::PalletId;
 use frame_system as system;
 use sp_core::H256;
 use sp_runtime::{
@@ -18,7 +19,8 @@ frame_support::construct_runtime!(
     {
         System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
         Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
-        StreamPayments: stream_payments::{Pallet, Call, Storage, Event<T>},
+        Assets: pallet_assets::{Pallet, Call, Storage, Config<T>, Event<T>},
+        StreamPayments: stream_payments::{Pallet, Call, Storage, Config<T>, Event<T>},
     }
 );
 
@@ -46,7 +48,7 @@ impl system::Config for Test {
     type SystemWeightInfo = ();
     type SS58Prefix = ConstU16<42>;
     type OnSetCode = ();
-    type MaxConsumers = frame_support::traits::ConstU32<16>;
+    type MaxConsumers = ConstU32<16>;
 }
 
 impl pallet_balances::Config for Test {
@@ -61,34 +63,113 @@ impl pallet_balances::Config for Test {
     type WeightInfo = ();
 }
 
+impl pallet_assets::Config for Test {
+    type Event = Event;
+    type Balance = u64;
+    type AssetId = u32;
+    type Currency = Balances;
+    type ForceOrigin = frame_system::EnsureRoot<u64>;
+    type AssetDeposit = ConstU64<1>;
+    type MetadataDepositBase = ConstU64<1>;
+    type MetadataDepositPerByte = ConstU64<1>;
+    type ApprovalDeposit = ConstU64<1>;
+    type StringLimit = ConstU32<50>;
+    type Freezer = ();
+    type Extra = ();
+    type WeightInfo = ();
+}
+
 pub const STREAM_DEPOSIT: u64 = 100;
 pub const MAX_STREAMS: u32 = 4;
+pub const MAX_RECIPIENTS: u32 = 3;
+pub const MAX_INCOMING_STREAMS: u32 = 5;
+pub const MAX_STREAMS_PER_BLOCK: u32 = 2;
+pub const PROPOSAL_LIFETIME: u64 = 10;
 
 frame_support::parameter_types! {
     pub const StreamDeposit: u64 = STREAM_DEPOSIT;
+    pub const StreamPaymentsPalletId: PalletId = PalletId(*b"py/strms");
     pub const MaxStreams: u32 = MAX_STREAMS;
+    pub const MaxRecipients: u32 = MAX_RECIPIENTS;
+    pub const MaxIncomingStreams: u32 = MAX_INCOMING_STREAMS;
+    pub const MaxStreamsPerBlock: u32 = MAX_STREAMS_PER_BLOCK;
+    pub const ProposalLifetime: u64 = PROPOSAL_LIFETIME;
+    pub static Hook: stream_payments::SettlementHook = stream_payments::SettlementHook::OnInitialize;
 }
 
 impl stream_payments::Config for Test {
     type Event = Event;
     type Currency = Balances;
+    type AssetId = u32;
+    type Assets = Assets;
     type StreamDeposit = StreamDeposit;
+    type PalletId = StreamPaymentsPalletId;
     type MaxStreams = MaxStreams;
+    type MaxRecipients = MaxRecipients;
+    type MaxIncomingStreams = MaxIncomingStreams;
+    type MaxStreamsPerBlock = MaxStreamsPerBlock;
+    type SettlementHook = Hook;
+    type ProposalLifetime = ProposalLifetime;
     type WeightInfo = stream_payments::weights::SubstrateWeight<Test>;
 }
 
 pub const A: u64 = 0;
 pub const B: u64 = 1;
+pub const C: u64 = 2;
+pub const D: u64 = 3;
 pub const INIT_BALANCE: u64 = 1_000_000;
+pub const ASSET: u32 = 7;
+
+/// A stream opened at genesis. [source, target, asset, spend_rate, rate, shares, options]
+pub type GenesisStream = (
+    u64,
+    u64,
+    stream_payments::StreamAsset<u32>,
+    u64,
+    stream_payments::StreamRate<u64>,
+    Vec<(u64, sp_runtime::Perbill)>,
+    stream_payments::StreamOptions<u64, u64>,
+);
+
+/// Test externalities checking the pallet invariants after every test.
+pub struct TestExternalities(sp_io::TestExternalities);
+
+impl TestExternalities {
+    /// Execute `execute` and check the invariants of the pallet storage afterwards.
+    pub fn execute_with<R>(&mut self, execute: impl FnOnce() -> R) -> R {
+        self.0.execute_with(|| {
+            let result = execute();
+            StreamPayments::do_try_state().unwrap();
+            result
+        })
+    }
+}
 
 #[allow(dead_code)]
-pub fn new_test_ext() -> sp_io::TestExternalities {
+pub fn new_test_ext() -> TestExternalities {
+    new_test_ext_with_streams(vec![])
+}
+
+pub fn new_test_ext_with_streams(streams: Vec<GenesisStream>) -> TestExternalities {
     let mut t = frame_system::GenesisConfig::default()
         .build_storage::<Test>()
         .unwrap();
     let genesis = pallet_balances::GenesisConfig::<Test> {
-        balances: vec![(A, INIT_BALANCE), (B, INIT_BALANCE)],
+        balances: vec![
+            (A, INIT_BALANCE),
+            (B, INIT_BALANCE),
+            (C, INIT_BALANCE),
+            (D, INIT_BALANCE),
+        ],
+    };
+    genesis.assimilate_storage(&mut t).unwrap();
+    let genesis = pallet_assets::GenesisConfig::<Test> {
+        assets: vec![(ASSET, A, true, 1)],
+        metadata: vec![],
+        accounts: vec![(ASSET, A, INIT_BALANCE), (ASSET, B, INIT_BALANCE)],
     };
     genesis.assimilate_storage(&mut t).unwrap();
-    t.into()
+    let genesis = stream_payments::GenesisConfig::<Test> { streams };
+    genesis.assimilate_storage(&mut t).unwrap();
+    TestExternalities(t.into())
 }
@@ -0,0 +1,70 @@
+//! Implementation of the `nonfungibles` traits, exposing the receiving side of every stream as
+//! a non-fungible item.
+//!
+//! The pallet forms a single collection, identified by `()`, and every stream is an item
+//! identified by the stream identifier. The owner of an item is the target of the stream.
+//! Transferring an item assigns the stream to a new target, exactly like
+//! `transfer_stream_target`. The following SCALE-encoded attributes are exposed:
+//!
+//! * `source` – The source of the stream.
+//! * `spend_rate` – The amount paid on every payment.
+//! * `total_paid` – The total amount paid so far.
+//! * `remaining` – The unspent part of the cap. Only present for capped streams.
+
+use super::*;
+use codec::Encode;
+use frame_support::{
+    dispatch::DispatchResult,
+    traits::tokens::nonfungibles::{Inspect, Transfer},
+};
+
+impl<T: Config> Pallet<T> {
+    /// The source and the stream identified by `id`, if it is open.
+    fn stream_by_id(id: StreamId) -> Option<(T::AccountId, StreamOf<T>)> {
+        let source = Self::stream_source(id)?;
+        let stream = Self::streams(&source)
+            .iter()
+            .find(|stream| stream.id == id)
+            .cloned()?;
+        Some((source, stream))
+    }
+}
+
+impl<T: Config> Inspect<T::AccountId> for Pallet<T> {
+    type InstanceId = StreamId;
+    type ClassId = ();
+
+    fn owner(_class: &Self::ClassId, instance: &Self::InstanceId) -> Option<T::AccountId> {
+        Self::stream_by_id(*instance).map(|(_, stream)| stream.target)
+    }
+
+    fn attribute(
+        _class: &Self::ClassId,
+        instance: &Self::InstanceId,
+        key: &[u8],
+    ) -> Option<Vec<u8>> {
+        let (source, stream) = Self::stream_by_id(*instance)?;
+        match key {
+            b"source" => Some(source.encode()),
+            b"spend_rate" => Some(stream.spend_rate.encode()),
+            b"total_paid" => Some(stream.total_paid.encode()),
+            b"remaining" => stream.remaining.map(|remaining| remaining.encode()),
+            _ => None,
+        }
+    }
+
+    fn can_transfer(_class: &Self::ClassId, instance: &Self::InstanceId) -> bool {
+        Self::stream_source(*instance).is_some()
+    }
+}
+
+impl<T: Config> Transfer<T::AccountId> for Pallet<T> {
+    fn transfer(
+        _class: &Self::ClassId,
+        instance: &Self::InstanceId,
+        destination: &T::AccountId,
+    ) -> DispatchResult {
+        let owner = Self::owner(&(), instance).ok_or(Error::<T>::StreamNotFound)?;
+        Self::do_transfer_target(owner, *instance, destination.clone())
+    }
+}
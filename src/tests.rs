@@ -1,7 +1,19 @@
 use crate::mock::*;
-use crate::{pallet, Error, Event as StreamPaymentsEvent, Stream};
-use frame_support::traits::OnInitialize;
+use crate::{
+    migrations, pallet, Config, Error, Event as StreamPaymentsEvent, IncomingPolicy,
+    SettlementHook, Stream, StreamAsset, StreamOptions, StreamRate, StreamState, WeightInfo,
+};
+use codec::Encode;
+use frame_support::storage::unhashed;
+use frame_support::traits::tokens::nonfungibles::{Inspect, Transfer};
+use frame_support::traits::{
+    Currency, GetStorageVersion, LockableCurrency, OnIdle, OnInitialize, OnRuntimeUpgrade,
+    ReservableCurrency, StorageVersion, WithdrawReasons,
+};
+use frame_support::weights::{GetDispatchInfo, Weight};
 use frame_support::{assert_noop, assert_ok};
+use sp_runtime::Perbill;
+use std::collections::BTreeMap;
 
 fn last_event() -> StreamPaymentsEvent<Test> {
     System::events()
@@ -20,6 +32,16 @@ fn last_event() -> StreamPaymentsEvent<Test> {
 
 const SPEND_RATE: u64 = INIT_BALANCE / 1000;
 
+/// Move to block `n` and settle all open streams, as a keeper would.
+fn settle_all_at(n: u64) {
+    System::set_block_number(n);
+    let mut ids: Vec<_> = <pallet::StreamSources<Test>>::iter_keys().collect();
+    ids.sort();
+    for id in ids {
+        assert_ok!(StreamPayments::settle_stream(Origin::signed(C), id));
+    }
+}
+
 #[test]
 fn genesis_config() {
     new_test_ext().execute_with(|| {
@@ -27,6 +49,158 @@ fn genesis_config() {
     });
 }
 
+#[test]
+fn genesis_streams() {
+    let capped = StreamOptions {
+        max_amount: Some(10 * SPEND_RATE),
+        ..Default::default()
+    };
+    new_test_ext_with_streams(vec![
+        (
+            A,
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamRate::Fixed,
+            vec![],
+            Default::default(),
+        ),
+        (
+            A,
+            C,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamRate::Fixed,
+            vec![],
+            capped,
+        ),
+        (
+            B,
+            A,
+            StreamAsset::Asset(ASSET),
+            SPEND_RATE,
+            StreamRate::Fixed,
+            vec![],
+            Default::default(),
+        ),
+    ])
+    .execute_with(|| {
+        assert_eq!(StreamPayments::streams(A).len(), 2);
+        assert_eq!(StreamPayments::streams(B)[0].id, 2);
+        assert_eq!(StreamPayments::next_stream_id(), 3);
+        assert_eq!(StreamPayments::incoming_streams(C).into_inner(), [(A, 1)]);
+        assert_eq!(
+            Balances::reserved_balance(A),
+            2 * STREAM_DEPOSIT + 10 * SPEND_RATE
+        );
+        assert_eq!(Balances::reserved_balance(B), STREAM_DEPOSIT);
+
+        // Genesis streams are paid from the first block on
+        settle_all_at(1);
+        assert_eq!(Balances::free_balance(C), INIT_BALANCE + SPEND_RATE);
+        assert_eq!(Assets::balance(ASSET, A), INIT_BALANCE + SPEND_RATE);
+    });
+}
+
+#[test]
+fn genesis_split_and_share_streams() {
+    let share = StreamRate::Share {
+        share: Perbill::from_percent(1),
+        floor: INIT_BALANCE / 2,
+    };
+    new_test_ext_with_streams(vec![
+        (
+            A,
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamRate::Fixed,
+            vec![(C, Perbill::from_percent(40))],
+            Default::default(),
+        ),
+        (
+            A,
+            D,
+            StreamAsset::Native,
+            0,
+            share,
+            vec![],
+            Default::default(),
+        ),
+    ])
+    .execute_with(|| {
+        assert_eq!(
+            StreamPayments::stream_shares(0).unwrap().into_inner(),
+            [(C, Perbill::from_percent(40))]
+        );
+        assert_eq!(StreamPayments::streams(A)[1].rate, share);
+        assert_eq!(Balances::reserved_balance(A), 2 * STREAM_DEPOSIT);
+
+        settle_all_at(1);
+        assert_eq!(
+            Balances::free_balance(B),
+            INIT_BALANCE + SPEND_RATE * 6 / 10
+        );
+        assert_eq!(
+            Balances::free_balance(C),
+            INIT_BALANCE + SPEND_RATE * 4 / 10
+        );
+        let balance = INIT_BALANCE - 2 * STREAM_DEPOSIT - SPEND_RATE;
+        assert_eq!(Balances::free_balance(D), INIT_BALANCE + balance / 100);
+    });
+}
+
+#[test]
+#[should_panic(expected = "Invalid genesis stream")]
+fn invalid_genesis_stream() {
+    new_test_ext_with_streams(vec![(
+        A,
+        A,
+        StreamAsset::Native,
+        SPEND_RATE,
+        StreamRate::Fixed,
+        vec![],
+        Default::default(),
+    )]);
+}
+
+#[test]
+#[should_panic(expected = "Invalid genesis stream")]
+fn split_share_genesis_stream() {
+    new_test_ext_with_streams(vec![(
+        A,
+        B,
+        StreamAsset::Native,
+        0,
+        StreamRate::Share {
+            share: Perbill::from_percent(1),
+            floor: 0,
+        },
+        vec![(C, Perbill::from_percent(50))],
+        Default::default(),
+    )]);
+}
+
+#[test]
+#[should_panic(expected = "Invalid genesis stream")]
+fn genesis_stream_limit_reached() {
+    new_test_ext_with_streams(
+        (0..=MAX_STREAMS)
+            .map(|_| {
+                (
+                    A,
+                    B,
+                    StreamAsset::Native,
+                    SPEND_RATE,
+                    StreamRate::Fixed,
+                    vec![],
+                    Default::default(),
+                )
+            })
+            .collect(),
+    );
+}
+
 #[test]
 fn open_stream() {
     new_test_ext().execute_with(|| {
@@ -34,20 +208,39 @@ fn open_stream() {
         assert_ok!(StreamPayments::open_stream(
             Origin::signed(A),
             B,
-            SPEND_RATE
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
         ));
         assert_eq!(
             *StreamPayments::streams(A),
             [Stream {
+                id: 0,
                 target: B,
-                spend_rate: SPEND_RATE
+                asset: StreamAsset::Native,
+                spend_rate: SPEND_RATE,
+                rate: StreamRate::Fixed,
+                pending_rate: None,
+                remaining: None,
+                started_at: 1,
+                ends_at: None,
+                cliff: None,
+                accrued: (0, 0),
+                total_paid: 0,
+                payments_made: 0,
+                period: 1,
+                grace: 0,
+                escrowed: false,
+                missed: 0,
+                last_settled: 1,
+                state: StreamState::Active
             }]
         );
         assert_eq!(Balances::free_balance(A), INIT_BALANCE - STREAM_DEPOSIT);
         assert_eq!(Balances::reserved_balance(A), STREAM_DEPOSIT);
         assert_eq!(
             last_event(),
-            StreamPaymentsEvent::StreamOpened(A, B, SPEND_RATE)
+            StreamPaymentsEvent::StreamOpened(0, A, B, StreamAsset::Native, SPEND_RATE)
         );
     });
 }
@@ -60,21 +253,281 @@ fn stream_limit_reached() {
             assert_ok!(StreamPayments::open_stream(
                 Origin::signed(A),
                 B,
-                SPEND_RATE
+                StreamAsset::Native,
+                SPEND_RATE,
+                Default::default()
             ));
         }
         assert_noop!(
-            StreamPayments::open_stream(Origin::signed(A), B, SPEND_RATE),
+            StreamPayments::open_stream(
+                Origin::signed(A),
+                B,
+                StreamAsset::Native,
+                SPEND_RATE,
+                Default::default()
+            ),
             Error::<Test>::StreamLimitReached
         );
     });
 }
 
+#[test]
+fn incoming_streams() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        for _ in 0..MAX_STREAMS {
+            assert_ok!(StreamPayments::open_stream(
+                Origin::signed(A),
+                D,
+                StreamAsset::Native,
+                SPEND_RATE,
+                Default::default()
+            ));
+        }
+        for (source, target) in [(B, C), (C, A)] {
+            assert_ok!(StreamPayments::open_stream(
+                Origin::signed(source),
+                target,
+                StreamAsset::Native,
+                SPEND_RATE,
+                Default::default()
+            ));
+        }
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(B),
+            D,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+        assert_eq!(
+            StreamPayments::incoming_streams(D).into_inner(),
+            [(A, 0), (A, 1), (A, 2), (A, 3), (B, 6)]
+        );
+        assert_eq!(StreamPayments::incoming_streams(C).into_inner(), [(B, 4)]);
+
+        // Targets cannot be spammed with streams
+        assert_noop!(
+            StreamPayments::open_stream(
+                Origin::signed(C),
+                D,
+                StreamAsset::Native,
+                SPEND_RATE,
+                Default::default()
+            ),
+            Error::<Test>::IncomingStreamLimitReached
+        );
+
+        // Closed and exhausted streams are removed from the index
+        assert_ok!(StreamPayments::close_stream(Origin::signed(A), 1));
+        assert_ok!(Balances::transfer(
+            Origin::signed(B),
+            A,
+            INIT_BALANCE - 2 * STREAM_DEPOSIT
+        ));
+        settle_all_at(2);
+        assert_eq!(
+            StreamPayments::incoming_streams(D).into_inner(),
+            [(A, 0), (A, 2), (A, 3)]
+        );
+        assert_eq!(*StreamPayments::incoming_streams(C), []);
+
+        // Transferred streams are moved between targets and sources
+        assert_ok!(StreamPayments::transfer_stream_target(
+            Origin::signed(D),
+            0,
+            C
+        ));
+        assert_ok!(StreamPayments::transfer_stream_source(
+            Origin::signed(A),
+            0,
+            D
+        ));
+        assert_ok!(StreamPayments::accept_stream_source(
+            Origin::signed(D),
+            0,
+            SPEND_RATE,
+            0
+        ));
+        assert_eq!(StreamPayments::incoming_streams(C).into_inner(), [(D, 0)]);
+        assert_eq!(
+            StreamPayments::incoming_streams(D).into_inner(),
+            [(A, 2), (A, 3)]
+        );
+    });
+}
+
+#[test]
+fn incoming_split_streams() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_split_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            vec![(C, Perbill::from_percent(30))],
+            Default::default()
+        ));
+
+        // Recipients of split streams are indexed along with the target
+        assert_eq!(StreamPayments::incoming_streams(B).into_inner(), [(A, 0)]);
+        assert_eq!(StreamPayments::incoming_streams(C).into_inner(), [(A, 0)]);
+        let by_recipient = StreamPayments::streams_by_target(C);
+        assert_eq!(by_recipient.len(), 1);
+        assert_eq!(by_recipient[0].0, A);
+        assert_eq!(by_recipient[0].1.id, 0);
+
+        // and count towards their limit of incoming streams
+        for _ in 1..MAX_INCOMING_STREAMS {
+            assert_ok!(StreamPayments::open_stream(
+                Origin::signed(B),
+                C,
+                StreamAsset::Native,
+                SPEND_RATE,
+                Default::default()
+            ));
+        }
+        assert_noop!(
+            StreamPayments::open_split_stream(
+                Origin::signed(D),
+                A,
+                StreamAsset::Native,
+                SPEND_RATE,
+                vec![(C, Perbill::from_percent(10))],
+                Default::default()
+            ),
+            Error::<Test>::IncomingStreamLimitReached
+        );
+
+        // A recipient already paid by the stream can become its target
+        assert_ok!(StreamPayments::transfer_stream_target(
+            Origin::signed(B),
+            0,
+            C
+        ));
+        assert!(StreamPayments::incoming_streams(B).is_empty());
+        assert_eq!(
+            StreamPayments::incoming_streams(C).len() as u32,
+            MAX_INCOMING_STREAMS
+        );
+
+        // The entries of recipients follow the source and are removed with the stream
+        assert_ok!(StreamPayments::transfer_stream_source(
+            Origin::signed(A),
+            0,
+            D
+        ));
+        assert_ok!(StreamPayments::accept_stream_source(
+            Origin::signed(D),
+            0,
+            SPEND_RATE,
+            0
+        ));
+        assert_eq!(StreamPayments::incoming_streams(C)[0], (D, 0));
+        assert_ok!(StreamPayments::close_stream(Origin::signed(D), 0));
+        assert_eq!(
+            StreamPayments::incoming_streams(C).into_inner(),
+            [(B, 1), (B, 2), (B, 3), (B, 4)]
+        );
+    });
+}
+
+#[test]
+fn stream_queries() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            C,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                period: 2,
+                ..Default::default()
+            }
+        ));
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                max_amount: Some(2 * SPEND_RATE),
+                ..Default::default()
+            }
+        ));
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Asset(ASSET),
+            SPEND_RATE,
+            Default::default()
+        ));
+
+        let ids = |streams: Vec<Stream<_, _, _, _>>| -> Vec<_> {
+            streams.into_iter().map(|stream| stream.id).collect()
+        };
+        assert_eq!(ids(StreamPayments::streams_by_source(A)), [0, 1, 2, 3]);
+        let incoming = StreamPayments::streams_by_target(B);
+        assert_eq!(
+            incoming
+                .iter()
+                .map(|(source, stream)| (*source, stream.id))
+                .collect::<Vec<_>>(),
+            [(A, 0), (A, 2), (A, 3)]
+        );
+
+        // The capped stream is committed but paid from the reserve
+        assert_eq!(
+            StreamPayments::outflow_per_block(A, StreamAsset::Native),
+            5 * SPEND_RATE / 2
+        );
+        assert_eq!(
+            StreamPayments::outflow_per_block(A, StreamAsset::Asset(ASSET)),
+            SPEND_RATE
+        );
+        let free = Balances::free_balance(A);
+        assert_eq!(
+            StreamPayments::runway(A, StreamAsset::Native),
+            Some(free / (3 * SPEND_RATE / 2))
+        );
+        assert_eq!(StreamPayments::runway(B, StreamAsset::Native), None);
+
+        // Projecting payments changes nothing
+        let projected = StreamPayments::projected_payments(4);
+        assert_eq!(
+            projected.into_iter().collect::<BTreeMap<_, _>>(),
+            BTreeMap::from([
+                (0, 4 * SPEND_RATE),
+                (1, 2 * SPEND_RATE),
+                (2, 2 * SPEND_RATE),
+                (3, 4 * SPEND_RATE),
+            ])
+        );
+        assert_eq!(Balances::free_balance(A), free);
+        assert_eq!(StreamPayments::streams(A)[0].total_paid, 0);
+    });
+}
+
 #[test]
 fn reflexive_stream() {
     new_test_ext().execute_with(|| {
         assert_noop!(
-            StreamPayments::open_stream(Origin::signed(A), A, SPEND_RATE),
+            StreamPayments::open_stream(
+                Origin::signed(A),
+                A,
+                StreamAsset::Native,
+                SPEND_RATE,
+                Default::default()
+            ),
             Error::<Test>::ReflexiveStream
         );
     });
@@ -84,7 +537,13 @@ fn reflexive_stream() {
 fn insufficient_balance() {
     new_test_ext().execute_with(|| {
         assert_noop!(
-            StreamPayments::open_stream(Origin::signed(A), B, INIT_BALANCE - STREAM_DEPOSIT + 1),
+            StreamPayments::open_stream(
+                Origin::signed(A),
+                B,
+                StreamAsset::Native,
+                INIT_BALANCE - STREAM_DEPOSIT + 1,
+                Default::default()
+            ),
             Error::<Test>::InsufficientBalance
         );
     });
@@ -97,7 +556,9 @@ fn close_stream() {
         assert_ok!(StreamPayments::open_stream(
             Origin::signed(A),
             B,
-            SPEND_RATE
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
         ));
         assert_ok!(StreamPayments::close_stream(Origin::signed(A), 0));
         assert_eq!(*StreamPayments::streams(A), []);
@@ -105,7 +566,67 @@ fn close_stream() {
         assert_eq!(Balances::reserved_balance(A), 0);
         assert_eq!(
             last_event(),
-            StreamPaymentsEvent::StreamClosed(A, B, SPEND_RATE)
+            StreamPaymentsEvent::StreamClosed(0, A, B, SPEND_RATE, 0, 0)
+        );
+    });
+}
+
+#[test]
+fn close_stream_by_id() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        for spend_rate in [SPEND_RATE, 2 * SPEND_RATE, 3 * SPEND_RATE] {
+            assert_ok!(StreamPayments::open_stream(
+                Origin::signed(A),
+                B,
+                StreamAsset::Native,
+                spend_rate,
+                Default::default()
+            ));
+        }
+        // Closing a stream does not change the identifiers of the remaining ones
+        assert_ok!(StreamPayments::close_stream(Origin::signed(A), 0));
+        assert_ok!(StreamPayments::close_stream(Origin::signed(A), 2));
+        assert_eq!(
+            *StreamPayments::streams(A),
+            [Stream {
+                id: 1,
+                target: B,
+                asset: StreamAsset::Native,
+                spend_rate: 2 * SPEND_RATE,
+                rate: StreamRate::Fixed,
+                pending_rate: None,
+                remaining: None,
+                started_at: 1,
+                ends_at: None,
+                cliff: None,
+                accrued: (0, 0),
+                total_paid: 0,
+                payments_made: 0,
+                period: 1,
+                grace: 0,
+                escrowed: false,
+                missed: 0,
+                last_settled: 1,
+                state: StreamState::Active
+            }]
+        );
+        assert_noop!(
+            StreamPayments::close_stream(Origin::signed(A), 0),
+            Error::<Test>::StreamNotFound
+        );
+
+        // Identifiers are never reused
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(B),
+            A,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamOpened(3, B, A, StreamAsset::Native, SPEND_RATE)
         );
     });
 }
@@ -121,6 +642,24 @@ fn stream_not_found() {
     });
 }
 
+#[test]
+fn close_stream_of_another_source() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+        assert_noop!(
+            StreamPayments::close_stream(Origin::signed(B), 0),
+            Error::<Test>::StreamNotFound
+        );
+    });
+}
+
 #[test]
 fn payment_made() {
     new_test_ext().execute_with(|| {
@@ -128,15 +667,16 @@ fn payment_made() {
         assert_ok!(StreamPayments::open_stream(
             Origin::signed(A),
             B,
-            SPEND_RATE
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
         ));
 
         // Tick the clock - step one block
-        System::set_block_number(2);
-        <StreamPayments as OnInitialize<u64>>::on_initialize(2);
+        settle_all_at(2);
         assert_eq!(
             last_event(),
-            StreamPaymentsEvent::PaymentMade(A, B, SPEND_RATE)
+            StreamPaymentsEvent::PaymentMade(0, A, B, SPEND_RATE)
         );
         assert_eq!(
             Balances::free_balance(A),
@@ -148,10 +688,11 @@ fn payment_made() {
         assert_ok!(StreamPayments::open_stream(
             Origin::signed(B),
             A,
-            SPEND_RATE * 10
+            StreamAsset::Native,
+            SPEND_RATE * 10,
+            Default::default()
         ));
-        System::set_block_number(2);
-        <StreamPayments as OnInitialize<u64>>::on_initialize(2);
+        settle_all_at(3);
         assert_eq!(
             Balances::free_balance(A),
             INIT_BALANCE - 2 * SPEND_RATE + 10 * SPEND_RATE - STREAM_DEPOSIT
@@ -172,17 +713,18 @@ fn stream_exhausted() {
         assert_ok!(StreamPayments::open_stream(
             Origin::signed(A),
             B,
-            spend_rate
+            StreamAsset::Native,
+            spend_rate,
+            Default::default()
         ));
 
         // Step two blocks - the second transfer should fail
         for i in [2, 3] {
-            System::set_block_number(i);
-            <StreamPayments as OnInitialize<u64>>::on_initialize(i);
+            settle_all_at(i);
         }
         assert_eq!(
             last_event(),
-            StreamPaymentsEvent::StreamExhausted(A, B, spend_rate,)
+            StreamPaymentsEvent::StreamExhausted(0, A, B, spend_rate, spend_rate, 1)
         );
         assert_eq!(Balances::free_balance(A), STREAM_DEPOSIT);
         assert_eq!(Balances::free_balance(B), INIT_BALANCE + spend_rate);
@@ -191,3 +733,2387 @@ fn stream_exhausted() {
         assert_eq!(*StreamPayments::streams(A), []);
     });
 }
+
+#[test]
+fn capped_stream_completed() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        let max_amount = 5 * SPEND_RATE / 2;
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                max_amount: Some(max_amount),
+                ..Default::default()
+            }
+        ));
+        // The whole cap is reserved up front
+        assert_eq!(
+            Balances::free_balance(A),
+            INIT_BALANCE - STREAM_DEPOSIT - max_amount
+        );
+        assert_eq!(Balances::reserved_balance(A), STREAM_DEPOSIT + max_amount);
+
+        for i in [2, 3] {
+            settle_all_at(i);
+        }
+        assert_eq!(
+            StreamPayments::streams(A)[0].remaining,
+            Some(max_amount - 2 * SPEND_RATE)
+        );
+        assert_eq!(
+            Balances::reserved_balance(A),
+            STREAM_DEPOSIT + max_amount - 2 * SPEND_RATE
+        );
+
+        // The last payment only covers what is left of the cap
+        settle_all_at(4);
+        System::assert_has_event(Event::StreamPayments(StreamPaymentsEvent::PaymentMade(
+            0,
+            A,
+            B,
+            SPEND_RATE / 2,
+        )));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamCompleted(0, A, B, SPEND_RATE, max_amount, 3)
+        );
+        assert_eq!(*StreamPayments::streams(A), []);
+        assert_eq!(Balances::free_balance(A), INIT_BALANCE - max_amount);
+        assert_eq!(Balances::reserved_balance(A), 0);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + max_amount);
+    });
+}
+
+#[test]
+fn capped_stream_paid_from_reserve() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        let max_amount = 10 * SPEND_RATE;
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                max_amount: Some(max_amount),
+                ..Default::default()
+            }
+        ));
+        // Spending the whole free balance does not affect the capped stream
+        assert_ok!(Balances::transfer(
+            Origin::signed(A),
+            B,
+            INIT_BALANCE - STREAM_DEPOSIT - max_amount
+        ));
+
+        settle_all_at(2);
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::PaymentMade(0, A, B, SPEND_RATE)
+        );
+        assert_eq!(Balances::free_balance(A), 0);
+        assert_eq!(
+            Balances::reserved_balance(A),
+            STREAM_DEPOSIT + max_amount - SPEND_RATE
+        );
+    });
+}
+
+#[test]
+fn close_capped_stream() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                max_amount: Some(10 * SPEND_RATE),
+                ..Default::default()
+            }
+        ));
+        settle_all_at(2);
+
+        // The unspent part of the cap is returned together with the deposit
+        assert_ok!(StreamPayments::close_stream(Origin::signed(A), 0));
+        assert_eq!(Balances::free_balance(A), INIT_BALANCE - SPEND_RATE);
+        assert_eq!(Balances::reserved_balance(A), 0);
+    });
+}
+
+#[test]
+fn fund_capped_stream() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                max_amount: Some(3 * SPEND_RATE),
+                ..Default::default()
+            }
+        ));
+        settle_all_at(2);
+
+        // Topping up reserves the amount and extends the cap
+        assert_ok!(StreamPayments::top_up_stream(
+            Origin::signed(A),
+            0,
+            2 * SPEND_RATE
+        ));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamFunded(0, A, 2 * SPEND_RATE)
+        );
+        assert_eq!(
+            StreamPayments::streams(A)[0].remaining,
+            Some(4 * SPEND_RATE)
+        );
+        assert_eq!(
+            Balances::reserved_balance(A),
+            STREAM_DEPOSIT + 4 * SPEND_RATE
+        );
+
+        // Two more payments are due, so only the rest of the cap can be returned
+        System::set_block_number(4);
+        assert_noop!(
+            StreamPayments::reduce_funding(Origin::signed(A), 0, 3 * SPEND_RATE),
+            Error::<Test>::InsufficientFunding
+        );
+        assert_ok!(StreamPayments::reduce_funding(
+            Origin::signed(A),
+            0,
+            2 * SPEND_RATE
+        ));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamDefunded(0, A, 2 * SPEND_RATE)
+        );
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 3 * SPEND_RATE);
+        assert_eq!(StreamPayments::streams(A)[0].remaining, Some(0));
+        assert_eq!(Balances::reserved_balance(A), STREAM_DEPOSIT);
+
+        // Streams which are neither capped nor escrowed have no funding to change
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            C,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+        assert_noop!(
+            StreamPayments::reduce_funding(Origin::signed(A), 1, SPEND_RATE),
+            Error::<Test>::NotFunded
+        );
+    });
+}
+
+#[test]
+fn insufficient_balance_for_cap() {
+    new_test_ext().execute_with(|| {
+        assert_noop!(
+            StreamPayments::open_stream(
+                Origin::signed(A),
+                B,
+                StreamAsset::Native,
+                SPEND_RATE,
+                StreamOptions {
+                    max_amount: Some(INIT_BALANCE - STREAM_DEPOSIT + 1),
+                    ..Default::default()
+                }
+            ),
+            Error::<Test>::InsufficientBalance
+        );
+    });
+}
+
+#[test]
+fn asset_stream() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Asset(ASSET),
+            SPEND_RATE,
+            Default::default()
+        ));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamOpened(0, A, B, StreamAsset::Asset(ASSET), SPEND_RATE)
+        );
+        // The deposit is reserved in the native currency
+        assert_eq!(Balances::reserved_balance(A), STREAM_DEPOSIT);
+
+        for i in [2, 3] {
+            settle_all_at(i);
+        }
+        assert_eq!(Assets::balance(ASSET, A), INIT_BALANCE - 2 * SPEND_RATE);
+        assert_eq!(Assets::balance(ASSET, B), INIT_BALANCE + 2 * SPEND_RATE);
+        assert_eq!(Balances::free_balance(A), INIT_BALANCE - STREAM_DEPOSIT);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE);
+    });
+}
+
+#[test]
+fn asset_stream_exhausted() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_noop!(
+            StreamPayments::open_stream(
+                Origin::signed(C),
+                B,
+                StreamAsset::Asset(ASSET),
+                SPEND_RATE,
+                Default::default()
+            ),
+            Error::<Test>::InsufficientBalance
+        );
+
+        assert_ok!(Assets::transfer(Origin::signed(A), ASSET, C, SPEND_RATE));
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(C),
+            B,
+            StreamAsset::Asset(ASSET),
+            SPEND_RATE,
+            Default::default()
+        ));
+        for i in [2, 3] {
+            settle_all_at(i);
+        }
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamExhausted(0, C, B, SPEND_RATE, SPEND_RATE, 1)
+        );
+        assert_eq!(Assets::balance(ASSET, B), INIT_BALANCE + SPEND_RATE);
+        assert_eq!(Balances::free_balance(C), INIT_BALANCE);
+    });
+}
+
+#[test]
+fn capped_asset_stream() {
+    new_test_ext().execute_with(|| {
+        assert_noop!(
+            StreamPayments::open_stream(
+                Origin::signed(A),
+                B,
+                StreamAsset::Asset(ASSET),
+                SPEND_RATE,
+                StreamOptions {
+                    max_amount: Some(SPEND_RATE),
+                    ..Default::default()
+                }
+            ),
+            Error::<Test>::UnsupportedCap
+        );
+    });
+}
+
+#[test]
+fn stream_statistics() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+        for i in [2, 3, 4] {
+            settle_all_at(i);
+        }
+        let stream = StreamPayments::streams(A)[0].clone();
+        assert_eq!(stream.started_at, 1);
+        assert_eq!(stream.total_paid, 3 * SPEND_RATE);
+        assert_eq!(stream.payments_made, 3);
+
+        assert_ok!(StreamPayments::close_stream(Origin::signed(A), 0));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamClosed(0, A, B, SPEND_RATE, 3 * SPEND_RATE, 3)
+        );
+    });
+}
+
+#[test]
+fn vesting_with_cliff() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                max_amount: Some(7 * SPEND_RATE),
+                ends_at: Some(8),
+                cliff: Some(5),
+                ..Default::default()
+            }
+        ));
+
+        // Nothing is paid before the cliff
+        for i in [2, 3, 4] {
+            settle_all_at(i);
+        }
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE);
+
+        // Payments accrued so far are made at once in the cliff block
+        settle_all_at(5);
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::PaymentMade(0, A, B, 4 * SPEND_RATE)
+        );
+        assert_eq!(StreamPayments::streams(A)[0].payments_made, 4);
+
+        // Followed by regular payments until the end block
+        settle_all_at(6);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 5 * SPEND_RATE);
+        settle_all_at(8);
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamCompleted(0, A, B, SPEND_RATE, 7 * SPEND_RATE, 7)
+        );
+        assert_eq!(Balances::free_balance(A), INIT_BALANCE - 7 * SPEND_RATE);
+        assert_eq!(Balances::reserved_balance(A), 0);
+    });
+}
+
+#[test]
+fn pause_stream_before_cliff() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                cliff: Some(10),
+                ..Default::default()
+            }
+        ));
+
+        System::set_block_number(4);
+        assert_ok!(StreamPayments::pause_stream(Origin::signed(A), 0, None));
+        System::set_block_number(6);
+        assert_ok!(StreamPayments::resume_stream(Origin::signed(A), 0));
+        settle_all_at(9);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE);
+
+        // The payments due before the pause are made in the cliff block as well
+        settle_all_at(10);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 7 * SPEND_RATE);
+        assert_eq!(StreamPayments::streams(A)[0].payments_made, 7);
+    });
+}
+
+#[test]
+fn update_stream_before_cliff() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        let options = StreamOptions {
+            cliff: Some(6),
+            ..Default::default()
+        };
+        for target in [B, C] {
+            assert_ok!(StreamPayments::open_stream(
+                Origin::signed(A),
+                target,
+                StreamAsset::Native,
+                SPEND_RATE,
+                options.clone()
+            ));
+        }
+
+        // A change scheduled for block 4 and one taking effect after block 3
+        System::set_block_number(2);
+        assert_ok!(StreamPayments::update_stream(
+            Origin::signed(A),
+            0,
+            2 * SPEND_RATE,
+            Some(4)
+        ));
+        System::set_block_number(3);
+        assert_ok!(StreamPayments::update_stream(
+            Origin::signed(A),
+            1,
+            2 * SPEND_RATE,
+            None
+        ));
+        settle_all_at(5);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE);
+        assert_eq!(StreamPayments::streams(A)[0].accrued, (2, 2 * SPEND_RATE));
+        assert_eq!(StreamPayments::streams(A)[1].accrued, (2, 2 * SPEND_RATE));
+
+        // The payments due before the changes are still made at the old rate
+        settle_all_at(6);
+        for target in [B, C] {
+            assert_eq!(
+                Balances::free_balance(target),
+                INIT_BALANCE + 8 * SPEND_RATE
+            );
+        }
+        let stream = &StreamPayments::streams(A)[0];
+        assert_eq!(stream.accrued, (0, 0));
+        assert_eq!(stream.payments_made, 5);
+        assert_eq!(stream.total_paid, 8 * SPEND_RATE);
+    });
+}
+
+#[test]
+fn invalid_cliff_block() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(2);
+        for (cliff, ends_at) in [(2, None), (5, Some(4))] {
+            assert_noop!(
+                StreamPayments::open_stream(
+                    Origin::signed(A),
+                    B,
+                    StreamAsset::Native,
+                    SPEND_RATE,
+                    StreamOptions {
+                        ends_at,
+                        cliff: Some(cliff),
+                        ..Default::default()
+                    }
+                ),
+                Error::<Test>::InvalidCliffBlock
+            );
+        }
+    });
+}
+
+#[test]
+fn stream_ends_at() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                ends_at: Some(3),
+                ..Default::default()
+            }
+        ));
+
+        settle_all_at(2);
+        assert_eq!(StreamPayments::streams(A).len(), 1);
+
+        // The stream makes its last payment in the end block and is closed right after
+        settle_all_at(3);
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamCompleted(0, A, B, SPEND_RATE, 2 * SPEND_RATE, 2)
+        );
+        assert_eq!(*StreamPayments::streams(A), []);
+        assert_eq!(Balances::free_balance(A), INIT_BALANCE - 2 * SPEND_RATE);
+        assert_eq!(Balances::reserved_balance(A), 0);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 2 * SPEND_RATE);
+    });
+}
+
+#[test]
+fn invalid_end_block() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(5);
+        for ends_at in [4, 5] {
+            assert_noop!(
+                StreamPayments::open_stream(
+                    Origin::signed(A),
+                    B,
+                    StreamAsset::Native,
+                    SPEND_RATE,
+                    StreamOptions {
+                        ends_at: Some(ends_at),
+                        ..Default::default()
+                    }
+                ),
+                Error::<Test>::InvalidEndBlock
+            );
+        }
+    });
+}
+
+#[test]
+fn payment_period() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                period: 3,
+                ..Default::default()
+            }
+        ));
+
+        // Payments are only made every third block
+        for i in 2..=7 {
+            settle_all_at(i);
+        }
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 2 * SPEND_RATE);
+        let stream = StreamPayments::streams(A)[0].clone();
+        assert_eq!(stream.payments_made, 2);
+        assert_eq!(stream.period, 3);
+    });
+}
+
+#[test]
+fn invalid_period() {
+    new_test_ext().execute_with(|| {
+        assert_noop!(
+            StreamPayments::open_stream(
+                Origin::signed(A),
+                B,
+                StreamAsset::Native,
+                SPEND_RATE,
+                StreamOptions {
+                    period: 0,
+                    ..Default::default()
+                }
+            ),
+            Error::<Test>::InvalidPeriod
+        );
+    });
+}
+
+#[test]
+fn grace_period() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                grace: 2,
+                ..Default::default()
+            }
+        ));
+        // Leave A with enough funds for a single payment
+        assert_ok!(Balances::transfer(
+            Origin::signed(A),
+            B,
+            INIT_BALANCE - STREAM_DEPOSIT - 3 * SPEND_RATE / 2
+        ));
+
+        // Two missed payments are tolerated
+        for i in [2, 3, 4] {
+            settle_all_at(i);
+        }
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::PaymentFailed(
+                0,
+                A,
+                B,
+                SPEND_RATE,
+                Error::<Test>::InsufficientBalance.into()
+            )
+        );
+        assert_eq!(StreamPayments::streams(A)[0].missed, 2);
+
+        // A successful payment resets the counter
+        assert_ok!(Balances::transfer(Origin::signed(B), A, SPEND_RATE));
+        settle_all_at(5);
+        assert_eq!(StreamPayments::streams(A)[0].missed, 0);
+        assert_eq!(StreamPayments::streams(A)[0].payments_made, 2);
+
+        // The third consecutive missed payment closes the stream
+        for i in [6, 7, 8] {
+            settle_all_at(i);
+        }
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamExhausted(0, A, B, SPEND_RATE, 2 * SPEND_RATE, 2)
+        );
+        assert_eq!(*StreamPayments::streams(A), []);
+    });
+}
+
+#[test]
+fn lazy_settlement() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+
+        // Nothing is transferred until the stream is settled
+        System::set_block_number(11);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE);
+
+        // All payments due so far are made at once
+        assert_ok!(StreamPayments::settle_stream(Origin::signed(C), 0));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::PaymentMade(0, A, B, 10 * SPEND_RATE)
+        );
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 10 * SPEND_RATE);
+        let stream = StreamPayments::streams(A)[0].clone();
+        assert_eq!(stream.last_settled, 11);
+        assert_eq!(stream.payments_made, 10);
+
+        // Settling again in the same block is a no-op
+        assert_ok!(StreamPayments::settle_stream(Origin::signed(C), 0));
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 10 * SPEND_RATE);
+    });
+}
+
+#[test]
+fn settlement_limited_by_balance() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                grace: 5,
+                ..Default::default()
+            }
+        ));
+        assert_ok!(Balances::transfer(
+            Origin::signed(A),
+            B,
+            INIT_BALANCE - STREAM_DEPOSIT - 7 * SPEND_RATE / 2
+        ));
+
+        // Only the payments A can afford are made, the rest are missed
+        System::set_block_number(6);
+        assert_ok!(StreamPayments::settle_stream(Origin::signed(C), 0));
+        System::assert_has_event(Event::StreamPayments(StreamPaymentsEvent::PaymentMade(
+            0,
+            A,
+            B,
+            3 * SPEND_RATE,
+        )));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::PaymentFailed(
+                0,
+                A,
+                B,
+                2 * SPEND_RATE,
+                Error::<Test>::InsufficientBalance.into()
+            )
+        );
+        let stream = StreamPayments::streams(A)[0].clone();
+        assert_eq!(stream.payments_made, 3);
+        assert_eq!(stream.missed, 2);
+    });
+}
+
+#[test]
+fn escrow_stream() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        let escrow = 5 * SPEND_RATE / 2;
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                escrow: Some(escrow),
+                ..Default::default()
+            }
+        ));
+        let escrow_account = StreamPayments::escrow_account(0);
+        assert_eq!(Balances::free_balance(escrow_account), escrow);
+        assert_eq!(
+            Balances::free_balance(A),
+            INIT_BALANCE - STREAM_DEPOSIT - escrow
+        );
+
+        // Spending the whole free balance of the source does not affect the escrowed stream
+        assert_ok!(Balances::transfer(
+            Origin::signed(A),
+            C,
+            INIT_BALANCE - STREAM_DEPOSIT - escrow
+        ));
+        settle_all_at(3);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 2 * SPEND_RATE);
+        assert_eq!(Balances::free_balance(escrow_account), SPEND_RATE / 2);
+
+        // Only the source can top up the escrow account
+        assert_noop!(
+            StreamPayments::top_up_stream(Origin::signed(C), 0, SPEND_RATE),
+            Error::<Test>::StreamNotFound
+        );
+        assert_ok!(Balances::transfer(Origin::signed(C), A, 2 * SPEND_RATE));
+        assert_ok!(StreamPayments::top_up_stream(
+            Origin::signed(A),
+            0,
+            2 * SPEND_RATE
+        ));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamFunded(0, A, 2 * SPEND_RATE)
+        );
+        settle_all_at(4);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 3 * SPEND_RATE);
+
+        // Unspent funds can be taken back from the escrow account
+        assert_ok!(StreamPayments::reduce_funding(
+            Origin::signed(A),
+            0,
+            SPEND_RATE
+        ));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamDefunded(0, A, SPEND_RATE)
+        );
+        assert_eq!(Balances::free_balance(escrow_account), SPEND_RATE / 2);
+        assert_eq!(Balances::free_balance(A), SPEND_RATE);
+
+        // Closing the stream returns the remaining escrow together with the deposit
+        assert_ok!(StreamPayments::close_stream(Origin::signed(A), 0));
+        assert_eq!(Balances::free_balance(escrow_account), 0);
+        assert_eq!(
+            Balances::free_balance(A),
+            3 * SPEND_RATE / 2 + STREAM_DEPOSIT
+        );
+        assert_eq!(Balances::reserved_balance(A), 0);
+    });
+}
+
+#[test]
+fn escrow_stream_weight() {
+    let open = |escrow| {
+        pallet::Call::<Test>::open_stream {
+            target: B,
+            asset: StreamAsset::Native,
+            spend_rate: SPEND_RATE,
+            options: StreamOptions {
+                escrow,
+                ..Default::default()
+            },
+        }
+        .get_dispatch_info()
+        .weight
+    };
+    // Funding the escrow is charged on top of opening the stream
+    assert_eq!(open(None), <Test as Config>::WeightInfo::open_stream());
+    assert_eq!(
+        open(Some(SPEND_RATE)),
+        <Test as Config>::WeightInfo::open_escrowed_stream()
+    );
+}
+
+#[test]
+fn escrow_stream_exhausted() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                escrow: Some(SPEND_RATE),
+                ..Default::default()
+            }
+        ));
+        settle_all_at(2);
+        settle_all_at(3);
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamExhausted(0, A, B, SPEND_RATE, SPEND_RATE, 1)
+        );
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + SPEND_RATE);
+        assert_eq!(Balances::free_balance(A), INIT_BALANCE - SPEND_RATE);
+    });
+}
+
+#[test]
+fn invalid_escrow_stream() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_noop!(
+            StreamPayments::open_stream(
+                Origin::signed(A),
+                B,
+                StreamAsset::Native,
+                SPEND_RATE,
+                StreamOptions {
+                    escrow: Some(INIT_BALANCE),
+                    ..Default::default()
+                }
+            ),
+            Error::<Test>::InsufficientBalance
+        );
+        assert_noop!(
+            StreamPayments::open_stream(
+                Origin::signed(A),
+                B,
+                StreamAsset::Native,
+                SPEND_RATE,
+                StreamOptions {
+                    max_amount: Some(SPEND_RATE),
+                    escrow: Some(SPEND_RATE),
+                    ..Default::default()
+                }
+            ),
+            Error::<Test>::UnsupportedCap
+        );
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+        assert_noop!(
+            StreamPayments::top_up_stream(Origin::signed(A), 0, SPEND_RATE),
+            Error::<Test>::NotFunded
+        );
+    });
+}
+
+#[test]
+fn open_stream_reverted() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        // The deposit cannot be reserved from a locked balance
+        Balances::set_lock(*b"testlock", &A, INIT_BALANCE, WithdrawReasons::all());
+        assert_noop!(
+            StreamPayments::open_stream(
+                Origin::signed(A),
+                B,
+                StreamAsset::Native,
+                SPEND_RATE,
+                StreamOptions {
+                    escrow: Some(SPEND_RATE),
+                    ..Default::default()
+                }
+            ),
+            pallet_balances::Error::<Test>::LiquidityRestrictions
+        );
+        assert!(StreamPayments::streams(A).is_empty());
+        assert!(StreamPayments::incoming_streams(B).is_empty());
+        assert_eq!(StreamPayments::next_stream_id(), 0);
+
+        Balances::remove_lock(*b"testlock", &A);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+        assert_eq!(StreamPayments::streams(A)[0].id, 0);
+    });
+}
+
+#[test]
+fn escrow_non_sufficient_asset() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        let asset = ASSET + 1;
+        assert_ok!(Assets::force_create(Origin::root(), asset, A, false, 1));
+        assert_ok!(Assets::mint(Origin::signed(A), asset, A, INIT_BALANCE));
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Asset(asset),
+            SPEND_RATE,
+            StreamOptions {
+                escrow: Some(3 * SPEND_RATE),
+                ..Default::default()
+            }
+        ));
+
+        // The escrow account has no native balance, but is kept alive by the pallet
+        let escrow_account = StreamPayments::escrow_account(0);
+        assert_eq!(Balances::free_balance(escrow_account), 0);
+        assert_eq!(System::providers(&escrow_account), 1);
+        assert_eq!(Assets::balance(asset, escrow_account), 3 * SPEND_RATE);
+
+        settle_all_at(2);
+        assert_eq!(Assets::balance(asset, B), SPEND_RATE);
+
+        // and reaped once its funds are returned
+        assert_ok!(StreamPayments::close_stream(Origin::signed(A), 0));
+        assert_eq!(Assets::balance(asset, A), INIT_BALANCE - SPEND_RATE);
+        assert!(!System::account_exists(&escrow_account));
+    });
+}
+
+#[test]
+fn escrow_refund_failed() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Asset(ASSET),
+            SPEND_RATE,
+            StreamOptions {
+                escrow: Some(SPEND_RATE),
+                ..Default::default()
+            }
+        ));
+        let escrow_account = StreamPayments::escrow_account(0);
+        assert_ok!(Assets::freeze(Origin::signed(A), ASSET, escrow_account));
+
+        // The stream is closed even though its escrow is stuck
+        assert_ok!(StreamPayments::close_stream(Origin::signed(A), 0));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::EscrowRefundFailed(
+                0,
+                A,
+                SPEND_RATE,
+                pallet_assets::Error::<Test>::Frozen.into()
+            )
+        );
+        assert_eq!(Assets::balance(ASSET, escrow_account), SPEND_RATE);
+        assert_eq!(Balances::reserved_balance(A), 0);
+        assert!(StreamPayments::streams(A).is_empty());
+    });
+}
+
+#[test]
+fn withdraw() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+        System::set_block_number(4);
+        assert_noop!(
+            StreamPayments::withdraw(Origin::signed(C), 0),
+            Error::<Test>::NotStreamTarget
+        );
+        assert_ok!(StreamPayments::withdraw(Origin::signed(B), 0));
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 3 * SPEND_RATE);
+        assert_noop!(
+            StreamPayments::withdraw(Origin::signed(B), 1),
+            Error::<Test>::StreamNotFound
+        );
+    });
+}
+
+#[test]
+fn split_stream() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        let shares = vec![
+            (C, Perbill::from_percent(30)),
+            (D, Perbill::from_rational(1u32, 3u32)),
+        ];
+        assert_ok!(StreamPayments::open_split_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            shares,
+            Default::default()
+        ));
+        // A single deposit is reserved for all recipients
+        assert_eq!(Balances::reserved_balance(A), STREAM_DEPOSIT);
+
+        // Every recipient receives its share rounded down and the target receives the rest
+        settle_all_at(2);
+        for (recipient, amount) in [(C, 300), (D, 333), (B, 367)] {
+            System::assert_has_event(Event::StreamPayments(StreamPaymentsEvent::PaymentMade(
+                0, A, recipient, amount,
+            )));
+            assert_eq!(Balances::free_balance(recipient), INIT_BALANCE + amount);
+        }
+        assert_eq!(StreamPayments::streams(A)[0].total_paid, SPEND_RATE);
+
+        // Settlement is charged for the actual number of recipients only
+        System::set_block_number(3);
+        assert_eq!(
+            StreamPayments::settle_stream(Origin::signed(C), 0)
+                .unwrap()
+                .actual_weight,
+            Some(<Test as Config>::WeightInfo::settle_stream(2))
+        );
+
+        assert_ok!(StreamPayments::close_stream(Origin::signed(A), 0));
+        assert_eq!(StreamPayments::stream_shares(0), None);
+    });
+}
+
+#[test]
+fn invalid_split_stream() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        let open = |shares: Vec<(u64, Perbill)>| {
+            StreamPayments::open_split_stream(
+                Origin::signed(A),
+                B,
+                StreamAsset::Native,
+                SPEND_RATE,
+                shares,
+                Default::default(),
+            )
+        };
+        let share = Perbill::from_percent(10);
+        assert_noop!(
+            open(vec![(C, share); MAX_RECIPIENTS as usize + 1]),
+            Error::<Test>::TooManyRecipients
+        );
+        assert_noop!(
+            open(vec![
+                (C, Perbill::from_percent(60)),
+                (D, Perbill::from_percent(41))
+            ]),
+            Error::<Test>::InvalidShares
+        );
+        assert_noop!(open(vec![(A, share)]), Error::<Test>::ReflexiveStream);
+
+        assert_ok!(StreamPayments::set_incoming_policy(
+            Origin::signed(C),
+            IncomingPolicy::RejectAll
+        ));
+        assert_noop!(open(vec![(C, share)]), Error::<Test>::StreamNotAccepted);
+    });
+}
+
+#[test]
+fn share_stream() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        let floor = 800_000;
+        assert_ok!(StreamPayments::open_share_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            Perbill::from_percent(10),
+            floor,
+            Default::default()
+        ));
+        let balance = INIT_BALANCE - STREAM_DEPOSIT;
+
+        settle_all_at(2);
+        assert_eq!(Balances::free_balance(A), balance - balance / 10);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + balance / 10);
+
+        // Every payment is a share of the balance left after the previous one
+        System::set_block_number(3);
+        assert_eq!(
+            StreamPayments::settle_stream(Origin::signed(C), 0)
+                .unwrap()
+                .actual_weight,
+            Some(<Test as Config>::WeightInfo::settle_share_stream())
+        );
+        let balance = balance - balance / 10;
+        assert_eq!(Balances::free_balance(A), balance - balance / 10);
+
+        // The stream is closed once the floor is reached instead of being exhausted
+        settle_all_at(5);
+        let total_paid = INIT_BALANCE - STREAM_DEPOSIT - floor;
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamCompleted(0, A, B, 0, total_paid, 4)
+        );
+        assert_eq!(Balances::free_balance(A), floor + STREAM_DEPOSIT);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + total_paid);
+    });
+}
+
+#[test]
+fn invalid_share_stream() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_noop!(
+            StreamPayments::open_share_stream(
+                Origin::signed(A),
+                B,
+                StreamAsset::Native,
+                Perbill::from_percent(10),
+                INIT_BALANCE - STREAM_DEPOSIT,
+                Default::default()
+            ),
+            Error::<Test>::InsufficientBalance
+        );
+        assert_noop!(
+            StreamPayments::open_share_stream(
+                Origin::signed(A),
+                B,
+                StreamAsset::Native,
+                Perbill::from_percent(10),
+                0,
+                StreamOptions {
+                    max_amount: Some(SPEND_RATE),
+                    ..Default::default()
+                }
+            ),
+            Error::<Test>::UnsupportedCap
+        );
+
+        assert_ok!(StreamPayments::open_share_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            Perbill::from_percent(10),
+            0,
+            Default::default()
+        ));
+        assert_noop!(
+            StreamPayments::update_stream(Origin::signed(A), 0, SPEND_RATE, None),
+            Error::<Test>::NotFixedRate
+        );
+    });
+}
+
+#[test]
+fn reject_stream() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                max_amount: Some(10 * SPEND_RATE),
+                ..Default::default()
+            }
+        ));
+        System::set_block_number(3);
+        assert_noop!(
+            StreamPayments::reject_stream(Origin::signed(A), 0),
+            Error::<Test>::NotStreamTarget
+        );
+        assert_ok!(StreamPayments::reject_stream(Origin::signed(B), 0));
+        assert_eq!(last_event(), StreamPaymentsEvent::StreamRejected(0, A, B));
+        assert_eq!(*StreamPayments::streams(A), []);
+        assert_eq!(StreamPayments::stream_source(0), None);
+        // No payments are made and all funds are returned to the source
+        assert_eq!(Balances::free_balance(A), INIT_BALANCE);
+        assert_eq!(Balances::reserved_balance(A), 0);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE);
+        assert_noop!(
+            StreamPayments::reject_stream(Origin::signed(B), 0),
+            Error::<Test>::StreamNotFound
+        );
+    });
+}
+
+#[test]
+fn reject_share() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_split_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            vec![
+                (C, Perbill::from_percent(30)),
+                (D, Perbill::from_percent(20))
+            ],
+            Default::default()
+        ));
+        settle_all_at(2);
+
+        // A recipient can only reject its own share, while the stream goes on
+        System::set_block_number(3);
+        assert_ok!(StreamPayments::reject_stream(Origin::signed(C), 0));
+        assert_eq!(last_event(), StreamPaymentsEvent::ShareRejected(0, A, C));
+        assert_eq!(
+            StreamPayments::stream_shares(0).unwrap().into_inner(),
+            [(D, Perbill::from_percent(20))]
+        );
+        assert!(StreamPayments::incoming_streams(C).is_empty());
+        assert_noop!(
+            StreamPayments::reject_stream(Origin::signed(C), 0),
+            Error::<Test>::NotStreamTarget
+        );
+
+        // The target receives the rejected share from then on
+        settle_all_at(4);
+        assert_eq!(
+            Balances::free_balance(C),
+            INIT_BALANCE + SPEND_RATE * 3 / 10
+        );
+        assert_eq!(
+            Balances::free_balance(D),
+            INIT_BALANCE + SPEND_RATE * 6 / 10
+        );
+        assert_eq!(
+            Balances::free_balance(B),
+            INIT_BALANCE + SPEND_RATE * 5 / 10 + 2 * SPEND_RATE * 8 / 10
+        );
+
+        assert_ok!(StreamPayments::reject_stream(Origin::signed(D), 0));
+        assert_eq!(StreamPayments::stream_shares(0), None);
+        assert_ok!(StreamPayments::reject_stream(Origin::signed(B), 0));
+        assert_eq!(StreamPayments::stream_source(0), None);
+    });
+}
+
+#[test]
+fn incoming_policy() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::set_incoming_policy(
+            Origin::signed(B),
+            IncomingPolicy::RejectAll
+        ));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::IncomingPolicySet(B, IncomingPolicy::RejectAll)
+        );
+        assert_noop!(
+            StreamPayments::open_stream(
+                Origin::signed(A),
+                B,
+                StreamAsset::Native,
+                SPEND_RATE,
+                Default::default()
+            ),
+            Error::<Test>::StreamNotAccepted
+        );
+
+        // Only allowed sources can open streams
+        assert_ok!(StreamPayments::set_incoming_policy(
+            Origin::signed(B),
+            IncomingPolicy::AllowList
+        ));
+        assert_ok!(StreamPayments::set_allowed_source(
+            Origin::signed(B),
+            A,
+            true
+        ));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::AllowedSourceSet(B, A, true)
+        );
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+        assert_noop!(
+            StreamPayments::open_stream(
+                Origin::signed(C),
+                B,
+                StreamAsset::Native,
+                SPEND_RATE,
+                Default::default()
+            ),
+            Error::<Test>::StreamNotAccepted
+        );
+        assert_ok!(StreamPayments::set_allowed_source(
+            Origin::signed(B),
+            A,
+            false
+        ));
+        assert_noop!(
+            StreamPayments::open_stream(
+                Origin::signed(A),
+                B,
+                StreamAsset::Native,
+                SPEND_RATE,
+                Default::default()
+            ),
+            Error::<Test>::StreamNotAccepted
+        );
+
+        // The default policy is not stored
+        assert_ok!(StreamPayments::set_incoming_policy(
+            Origin::signed(B),
+            IncomingPolicy::AcceptAll
+        ));
+        assert_eq!(<pallet::IncomingPolicies<Test>>::iter().count(), 0);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(C),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+    });
+}
+
+#[test]
+fn accept_stream() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::set_incoming_policy(
+            Origin::signed(B),
+            IncomingPolicy::RequireAcceptance
+        ));
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamProposed(
+                0,
+                A,
+                B,
+                StreamAsset::Native,
+                SPEND_RATE,
+                1 + PROPOSAL_LIFETIME
+            )
+        );
+        assert_eq!(Balances::reserved_balance(A), STREAM_DEPOSIT);
+
+        // Pending streams are not paid
+        settle_all_at(3);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE);
+        assert_noop!(
+            StreamPayments::pause_stream(Origin::signed(A), 0, None),
+            Error::<Test>::StreamPending
+        );
+        assert_noop!(
+            StreamPayments::accept_stream(Origin::signed(C), 0),
+            Error::<Test>::NotStreamTarget
+        );
+
+        assert_ok!(StreamPayments::accept_stream(Origin::signed(B), 0));
+        assert_eq!(last_event(), StreamPaymentsEvent::StreamAccepted(0, A, B));
+        assert_noop!(
+            StreamPayments::accept_stream(Origin::signed(B), 0),
+            Error::<Test>::NotPending
+        );
+
+        // Payments become due from the next block on
+        settle_all_at(5);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 2 * SPEND_RATE);
+        assert_eq!(StreamPayments::streams(A)[0].started_at, 3);
+    });
+}
+
+#[test]
+fn stream_proposal_expired() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::set_incoming_policy(
+            Origin::signed(B),
+            IncomingPolicy::RequireAcceptance
+        ));
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+
+        System::set_block_number(1 + PROPOSAL_LIFETIME);
+        assert_noop!(
+            StreamPayments::accept_stream(Origin::signed(B), 0),
+            Error::<Test>::ProposalExpired
+        );
+        settle_all_at(1 + PROPOSAL_LIFETIME);
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamProposalExpired(0, A, B)
+        );
+        assert_eq!(*StreamPayments::streams(A), []);
+        assert_eq!(Balances::free_balance(A), INIT_BALANCE);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE);
+    });
+}
+
+#[test]
+fn claim() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+        System::set_block_number(4);
+        assert_noop!(
+            StreamPayments::claim(Origin::signed(C), 0, C),
+            Error::<Test>::NotStreamTarget
+        );
+        assert_ok!(StreamPayments::claim(Origin::signed(B), 0, C));
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE);
+        assert_eq!(Balances::free_balance(C), INIT_BALANCE + 3 * SPEND_RATE);
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::Claimed(0, B, C, 3 * SPEND_RATE, 1, 4)
+        );
+
+        // Only the payments due since the previous claim are transferred
+        System::set_block_number(6);
+        assert_ok!(StreamPayments::claim(Origin::signed(B), 0, C));
+        assert_eq!(Balances::free_balance(C), INIT_BALANCE + 5 * SPEND_RATE);
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::Claimed(0, B, C, 2 * SPEND_RATE, 4, 6)
+        );
+    });
+}
+
+#[test]
+fn claim_exhausted() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+        // Leave the source with funds for a single payment
+        assert_ok!(Balances::transfer(
+            Origin::signed(A),
+            C,
+            INIT_BALANCE - STREAM_DEPOSIT - SPEND_RATE
+        ));
+
+        System::set_block_number(4);
+        assert_ok!(StreamPayments::claim(Origin::signed(B), 0, C));
+        assert!(System::events().iter().any(|record| record.event
+            == Event::StreamPayments(StreamPaymentsEvent::StreamExhausted(
+                0,
+                A,
+                B,
+                2 * SPEND_RATE,
+                SPEND_RATE,
+                1
+            ))));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::Claimed(0, B, C, SPEND_RATE, 1, 4)
+        );
+        assert_eq!(*StreamPayments::streams(A), []);
+        assert_eq!(Balances::reserved_balance(A), 0);
+    });
+}
+
+#[test]
+fn transfer_stream_target() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+
+        System::set_block_number(3);
+        assert_noop!(
+            StreamPayments::transfer_stream_target(Origin::signed(C), 0, C),
+            Error::<Test>::NotStreamTarget
+        );
+        assert_noop!(
+            StreamPayments::transfer_stream_target(Origin::signed(B), 0, A),
+            Error::<Test>::ReflexiveStream
+        );
+        assert_ok!(StreamPayments::set_incoming_policy(
+            Origin::signed(D),
+            IncomingPolicy::RejectAll
+        ));
+        assert_noop!(
+            StreamPayments::transfer_stream_target(Origin::signed(B), 0, D),
+            Error::<Test>::StreamNotAccepted
+        );
+
+        // The payments due so far are made to the old target
+        assert_ok!(StreamPayments::transfer_stream_target(
+            Origin::signed(B),
+            0,
+            C
+        ));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamTargetTransferred(0, A, B, C)
+        );
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 2 * SPEND_RATE);
+        assert_eq!(StreamPayments::streams(A)[0].target, C);
+
+        settle_all_at(4);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 2 * SPEND_RATE);
+        assert_eq!(Balances::free_balance(C), INIT_BALANCE + SPEND_RATE);
+    });
+}
+
+#[test]
+fn transfer_stream_source() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        let max_amount = 10 * SPEND_RATE;
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                max_amount: Some(max_amount),
+                ..Default::default()
+            }
+        ));
+
+        System::set_block_number(3);
+        assert_noop!(
+            StreamPayments::transfer_stream_source(Origin::signed(B), 0, C),
+            Error::<Test>::StreamNotFound
+        );
+        assert_noop!(
+            StreamPayments::transfer_stream_source(Origin::signed(A), 0, B),
+            Error::<Test>::ReflexiveStream
+        );
+        assert_ok!(StreamPayments::transfer_stream_source(
+            Origin::signed(A),
+            0,
+            C
+        ));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamSourceTransferProposed(0, A, C)
+        );
+
+        // Only the proposed account can take the stream over
+        assert_noop!(
+            StreamPayments::accept_stream_source(Origin::signed(D), 0, SPEND_RATE, max_amount),
+            Error::<Test>::NoSourceTransfer
+        );
+        assert_ok!(StreamPayments::accept_stream_source(
+            Origin::signed(C),
+            0,
+            SPEND_RATE,
+            max_amount
+        ));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamSourceTransferred(0, A, C)
+        );
+        assert_eq!(StreamPayments::source_transfer(0), None);
+        assert_eq!(StreamPayments::stream_source(0), Some(C));
+        assert_eq!(*StreamPayments::streams(A), []);
+        assert_eq!(StreamPayments::streams(C)[0].id, 0);
+
+        // The payments due so far are made by the old source, which gets its funds back
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 2 * SPEND_RATE);
+        assert_eq!(Balances::free_balance(A), INIT_BALANCE - 2 * SPEND_RATE);
+        assert_eq!(Balances::reserved_balance(A), 0);
+        assert_eq!(
+            Balances::reserved_balance(C),
+            STREAM_DEPOSIT + max_amount - 2 * SPEND_RATE
+        );
+
+        settle_all_at(4);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 3 * SPEND_RATE);
+        assert_eq!(
+            Balances::reserved_balance(C),
+            STREAM_DEPOSIT + max_amount - 3 * SPEND_RATE
+        );
+    });
+}
+
+#[test]
+fn transfer_stream_source_to_target() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+        assert_ok!(StreamPayments::transfer_stream_source(
+            Origin::signed(A),
+            0,
+            C
+        ));
+
+        // The stream cannot be taken over once it has been assigned to the proposed source
+        assert_ok!(StreamPayments::transfer_stream_target(
+            Origin::signed(B),
+            0,
+            C
+        ));
+        assert_noop!(
+            StreamPayments::accept_stream_source(Origin::signed(C), 0, SPEND_RATE, 0),
+            Error::<Test>::ReflexiveStream
+        );
+        assert_eq!(StreamPayments::stream_source(0), Some(A));
+    });
+}
+
+#[test]
+fn transfer_escrowed_stream_source() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                escrow: Some(5 * SPEND_RATE),
+                ..Default::default()
+            }
+        ));
+        let escrow_account = StreamPayments::escrow_account(0);
+
+        System::set_block_number(3);
+        assert_ok!(StreamPayments::transfer_stream_source(
+            Origin::signed(A),
+            0,
+            C
+        ));
+        assert_ok!(StreamPayments::accept_stream_source(
+            Origin::signed(C),
+            0,
+            SPEND_RATE,
+            3 * SPEND_RATE
+        ));
+
+        // The new source pays the old one for what is left in the escrow account
+        assert_eq!(Balances::free_balance(escrow_account), 3 * SPEND_RATE);
+        assert_eq!(Balances::free_balance(A), INIT_BALANCE - 2 * SPEND_RATE);
+        assert_eq!(
+            Balances::free_balance(C),
+            INIT_BALANCE - STREAM_DEPOSIT - 3 * SPEND_RATE
+        );
+
+        // and gets it back once the stream is closed
+        settle_all_at(4);
+        assert_ok!(StreamPayments::close_stream(Origin::signed(C), 0));
+        assert_eq!(Balances::free_balance(escrow_account), 0);
+        assert_eq!(Balances::free_balance(A), INIT_BALANCE - 2 * SPEND_RATE);
+        assert_eq!(Balances::free_balance(C), INIT_BALANCE - SPEND_RATE);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 3 * SPEND_RATE);
+    });
+}
+
+#[test]
+fn transfer_stream_source_terms_changed() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                escrow: Some(5 * SPEND_RATE),
+                ..Default::default()
+            }
+        ));
+        assert_ok!(StreamPayments::transfer_stream_source(
+            Origin::signed(A),
+            0,
+            C
+        ));
+
+        // The new source does not pay for funds added after the proposal
+        assert_ok!(StreamPayments::top_up_stream(
+            Origin::signed(A),
+            0,
+            10 * SPEND_RATE
+        ));
+        System::set_block_number(3);
+        assert_noop!(
+            StreamPayments::accept_stream_source(Origin::signed(C), 0, SPEND_RATE, 5 * SPEND_RATE),
+            Error::<Test>::SourceTransferTermsChanged
+        );
+
+        // nor takes over a changed spend rate
+        assert_ok!(StreamPayments::update_stream(
+            Origin::signed(A),
+            0,
+            2 * SPEND_RATE,
+            None
+        ));
+        assert_noop!(
+            StreamPayments::accept_stream_source(Origin::signed(C), 0, SPEND_RATE, 13 * SPEND_RATE),
+            Error::<Test>::SourceTransferTermsChanged
+        );
+        assert_ok!(StreamPayments::update_stream(
+            Origin::signed(A),
+            0,
+            SPEND_RATE,
+            Some(5)
+        ));
+        assert_noop!(
+            StreamPayments::accept_stream_source(
+                Origin::signed(C),
+                0,
+                2 * SPEND_RATE,
+                13 * SPEND_RATE
+            ),
+            Error::<Test>::SourceTransferTermsChanged
+        );
+
+        // The stream is taken over once the terms are agreed to
+        System::set_block_number(5);
+        assert_ok!(StreamPayments::accept_stream_source(
+            Origin::signed(C),
+            0,
+            SPEND_RATE,
+            11 * SPEND_RATE
+        ));
+        assert_eq!(StreamPayments::stream_source(0), Some(C));
+    });
+}
+
+#[test]
+fn transfer_stream_source_not_accepted() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+        assert_ok!(StreamPayments::transfer_stream_source(
+            Origin::signed(A),
+            0,
+            C
+        ));
+
+        // The target must allow the new source just like for a new stream
+        assert_ok!(StreamPayments::set_incoming_policy(
+            Origin::signed(B),
+            IncomingPolicy::AllowList
+        ));
+        assert_ok!(StreamPayments::set_allowed_source(
+            Origin::signed(B),
+            A,
+            true
+        ));
+        assert_noop!(
+            StreamPayments::accept_stream_source(Origin::signed(C), 0, SPEND_RATE, 0),
+            Error::<Test>::StreamNotAccepted
+        );
+        assert_ok!(StreamPayments::set_allowed_source(
+            Origin::signed(B),
+            C,
+            true
+        ));
+        assert_ok!(StreamPayments::accept_stream_source(
+            Origin::signed(C),
+            0,
+            SPEND_RATE,
+            0
+        ));
+        assert_eq!(StreamPayments::stream_source(0), Some(C));
+    });
+}
+
+#[test]
+fn stream_as_nft() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        let max_amount = 10 * SPEND_RATE;
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                max_amount: Some(max_amount),
+                ..Default::default()
+            }
+        ));
+        settle_all_at(2);
+
+        assert_eq!(<StreamPayments as Inspect<u64>>::owner(&(), &0), Some(B));
+        assert_eq!(<StreamPayments as Inspect<u64>>::owner(&(), &1), None);
+        let attribute = |key: &[u8]| <StreamPayments as Inspect<u64>>::attribute(&(), &0, key);
+        assert_eq!(attribute(b"source"), Some(A.encode()));
+        assert_eq!(attribute(b"spend_rate"), Some(SPEND_RATE.encode()));
+        assert_eq!(attribute(b"total_paid"), Some(SPEND_RATE.encode()));
+        assert_eq!(
+            attribute(b"remaining"),
+            Some((max_amount - SPEND_RATE).encode())
+        );
+        assert_eq!(attribute(b"unknown"), None);
+
+        // Transferring the item assigns the stream to a new target
+        System::set_block_number(3);
+        assert_ok!(<StreamPayments as Transfer<u64>>::transfer(&(), &0, &C));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamTargetTransferred(0, A, B, C)
+        );
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 2 * SPEND_RATE);
+        assert_eq!(<StreamPayments as Inspect<u64>>::owner(&(), &0), Some(C));
+        assert_noop!(
+            <StreamPayments as Transfer<u64>>::transfer(&(), &1, &C),
+            Error::<Test>::StreamNotFound
+        );
+    });
+}
+
+#[test]
+fn close_stream_settles() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+        System::set_block_number(3);
+        assert_ok!(StreamPayments::close_stream(Origin::signed(A), 0));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamClosed(0, A, B, SPEND_RATE, 2 * SPEND_RATE, 2)
+        );
+        assert_eq!(Balances::free_balance(A), INIT_BALANCE - 2 * SPEND_RATE);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 2 * SPEND_RATE);
+        assert!(StreamPayments::stream_source(0).is_none());
+        assert!(!<pallet::Streams<Test>>::contains_key(A));
+    });
+}
+
+#[test]
+fn update_stream() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+
+        // Payments due before the update are made at the old rate
+        System::set_block_number(3);
+        assert_noop!(
+            StreamPayments::update_stream(Origin::signed(B), 0, 2 * SPEND_RATE, None),
+            Error::<Test>::StreamNotFound
+        );
+        assert_noop!(
+            StreamPayments::update_stream(Origin::signed(A), 0, INIT_BALANCE, None),
+            Error::<Test>::InsufficientBalance
+        );
+        assert_ok!(StreamPayments::update_stream(
+            Origin::signed(A),
+            0,
+            2 * SPEND_RATE,
+            None
+        ));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamUpdated(0, A, B, SPEND_RATE, 2 * SPEND_RATE, 4)
+        );
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 2 * SPEND_RATE);
+
+        settle_all_at(5);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 6 * SPEND_RATE);
+        // The stream keeps its identifier and statistics
+        let stream = &StreamPayments::streams(A)[0];
+        assert_eq!(stream.id, 0);
+        assert_eq!(stream.payments_made, 4);
+        assert_eq!(stream.total_paid, 6 * SPEND_RATE);
+    });
+}
+
+#[test]
+fn update_stream_later() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+
+        System::set_block_number(2);
+        assert_noop!(
+            StreamPayments::update_stream(Origin::signed(A), 0, 2 * SPEND_RATE, Some(2)),
+            Error::<Test>::InvalidEffectiveBlock
+        );
+        assert_ok!(StreamPayments::update_stream(
+            Origin::signed(A),
+            0,
+            2 * SPEND_RATE,
+            Some(5)
+        ));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamUpdated(0, A, B, SPEND_RATE, 2 * SPEND_RATE, 5)
+        );
+        assert_eq!(StreamPayments::streams(A)[0].spend_rate, SPEND_RATE);
+
+        // Payments in blocks 2 to 4 are made at the old rate, later ones at the new rate
+        settle_all_at(6);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 7 * SPEND_RATE);
+        assert_eq!(StreamPayments::streams(A)[0].spend_rate, 2 * SPEND_RATE);
+        assert_eq!(StreamPayments::streams(A)[0].pending_rate, None);
+    });
+}
+
+#[test]
+fn pause_stream() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+
+        // Payments due before the pause are made
+        System::set_block_number(3);
+        assert_noop!(
+            StreamPayments::pause_stream(Origin::signed(B), 0, None),
+            Error::<Test>::StreamNotFound
+        );
+        assert_noop!(
+            StreamPayments::resume_stream(Origin::signed(A), 0),
+            Error::<Test>::NotPaused
+        );
+        assert_ok!(StreamPayments::pause_stream(Origin::signed(A), 0, None));
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamPaused(0, A, B, None)
+        );
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 2 * SPEND_RATE);
+        assert_noop!(
+            StreamPayments::pause_stream(Origin::signed(A), 0, None),
+            Error::<Test>::AlreadyPaused
+        );
+
+        // No payments become due while paused, but the deposit stays reserved
+        settle_all_at(6);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 2 * SPEND_RATE);
+        assert_eq!(Balances::reserved_balance(A), STREAM_DEPOSIT);
+
+        assert_ok!(StreamPayments::resume_stream(Origin::signed(A), 0));
+        assert_eq!(last_event(), StreamPaymentsEvent::StreamResumed(0, A, B));
+        settle_all_at(8);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 4 * SPEND_RATE);
+        assert_eq!(StreamPayments::streams(A)[0].payments_made, 4);
+    });
+}
+
+#[test]
+fn resume_stream_automatically() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+
+        System::set_block_number(2);
+        assert_noop!(
+            StreamPayments::pause_stream(Origin::signed(A), 0, Some(2)),
+            Error::<Test>::InvalidResumeBlock
+        );
+        assert_ok!(StreamPayments::pause_stream(Origin::signed(A), 0, Some(5)));
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + SPEND_RATE);
+
+        settle_all_at(4);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + SPEND_RATE);
+
+        // Payments become due again after the resume block
+        settle_all_at(7);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 3 * SPEND_RATE);
+        assert_eq!(StreamPayments::streams(A)[0].state, StreamState::Active);
+        assert!(System::events().iter().any(|record| record.event
+            == Event::StreamPayments(StreamPaymentsEvent::StreamResumed(0, A, B))));
+    });
+}
+
+#[test]
+fn pause_stream_within_period() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                period: 3,
+                ..Default::default()
+            }
+        ));
+        settle_all_at(4);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + SPEND_RATE);
+
+        System::set_block_number(5);
+        assert_ok!(StreamPayments::pause_stream(Origin::signed(A), 0, Some(6)));
+
+        // The period ending in block 7 was partly paused, but is still paid in full
+        settle_all_at(6);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + SPEND_RATE);
+        settle_all_at(7);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 2 * SPEND_RATE);
+
+        // Payments stay aligned to the opening block
+        settle_all_at(9);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 2 * SPEND_RATE);
+        settle_all_at(10);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 3 * SPEND_RATE);
+        assert_eq!(StreamPayments::streams(A)[0].payments_made, 3);
+    });
+}
+
+#[test]
+fn paused_stream_ends() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                ends_at: Some(5),
+                ..Default::default()
+            }
+        ));
+        System::set_block_number(2);
+        assert_ok!(StreamPayments::pause_stream(Origin::signed(A), 0, None));
+
+        settle_all_at(5);
+        assert_eq!(
+            last_event(),
+            StreamPaymentsEvent::StreamCompleted(0, A, B, SPEND_RATE, SPEND_RATE, 1)
+        );
+        assert_eq!(*StreamPayments::streams(A), []);
+        assert_eq!(Balances::reserved_balance(A), 0);
+    });
+}
+
+#[test]
+fn settle_streams_per_block() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        for _ in 0..3 {
+            assert_ok!(StreamPayments::open_stream(
+                Origin::signed(A),
+                B,
+                StreamAsset::Native,
+                SPEND_RATE,
+                Default::default()
+            ));
+        }
+
+        // Only a limited number of streams is settled in a block
+        System::set_block_number(2);
+        <StreamPayments as OnInitialize<u64>>::on_initialize(2);
+        let lagging: Vec<_> = StreamPayments::streams(A)
+            .iter()
+            .filter(|stream| stream.last_settled == 1)
+            .map(|stream| stream.id)
+            .collect();
+        assert_eq!(lagging.len(), 3 - MAX_STREAMS_PER_BLOCK as usize);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 2 * SPEND_RATE);
+        assert!(StreamPayments::settlement_cursor().is_some());
+
+        // Processing resumes with the lagging stream, which pays its whole backlog
+        System::set_block_number(3);
+        <StreamPayments as OnInitialize<u64>>::on_initialize(3);
+        let stream = StreamPayments::streams(A)
+            .iter()
+            .find(|stream| stream.id == lagging[0])
+            .cloned()
+            .unwrap();
+        assert_eq!(stream.last_settled, 3);
+        assert_eq!(stream.payments_made, 2);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + 4 * SPEND_RATE);
+
+        // All streams have been reached, so the next block starts over
+        assert_eq!(StreamPayments::settlement_cursor(), None);
+    });
+}
+
+#[test]
+fn settle_streams_on_idle() {
+    new_test_ext().execute_with(|| {
+        Hook::set(&SettlementHook::OnIdle);
+        System::set_block_number(1);
+        for _ in 0..3 {
+            assert_ok!(StreamPayments::open_stream(
+                Origin::signed(A),
+                B,
+                StreamAsset::Native,
+                SPEND_RATE,
+                Default::default()
+            ));
+        }
+
+        // Nothing is settled before the extrinsics
+        System::set_block_number(2);
+        assert_eq!(<StreamPayments as OnInitialize<u64>>::on_initialize(2), 0);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE);
+
+        // Only as many streams as fit in the remaining weight are settled
+        let weight = <Test as Config>::WeightInfo::on_initialize_transfer(1, MAX_RECIPIENTS);
+        assert_eq!(<StreamPayments as OnIdle<u64>>::on_idle(2, weight - 1), 0);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE);
+        assert!(<StreamPayments as OnIdle<u64>>::on_idle(2, weight) <= weight);
+        assert_eq!(Balances::free_balance(B), INIT_BALANCE + SPEND_RATE);
+
+        // The streams left over are carried over to the next block
+        System::set_block_number(3);
+        <StreamPayments as OnIdle<u64>>::on_idle(3, Weight::MAX);
+        assert_eq!(
+            Balances::free_balance(B),
+            INIT_BALANCE + (1 + 2 * MAX_STREAMS_PER_BLOCK as u64) * SPEND_RATE
+        );
+    });
+}
+
+#[test]
+fn migrate_to_v1() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(7);
+        // Streams stored in the layout preceding stream identifiers: (target, spend_rate)
+        unhashed::put(
+            &<pallet::Streams<Test>>::hashed_key_for(A),
+            &vec![(B, SPEND_RATE), (B, 2 * SPEND_RATE)],
+        );
+        unhashed::put(
+            &<pallet::Streams<Test>>::hashed_key_for(B),
+            &vec![(A, 3 * SPEND_RATE)],
+        );
+        assert_ok!(Balances::reserve(&A, 2 * STREAM_DEPOSIT));
+        assert_ok!(Balances::reserve(&B, STREAM_DEPOSIT));
+        let before = migrations::stream_stats::<Test>();
+        assert_eq!(
+            before,
+            migrations::StreamStats {
+                sources: 2,
+                streams: 3,
+                excess: 0,
+                reserved: 3 * STREAM_DEPOSIT
+            }
+        );
+
+        migrations::Migration::<Test>::on_runtime_upgrade();
+        assert_eq!(migrations::check_upgrade::<Test>(before), Ok(()));
+
+        let mut streams: Vec<_> = <pallet::Streams<Test>>::iter()
+            .flat_map(|(source, streams)| {
+                streams
+                    .into_inner()
+                    .into_iter()
+                    .map(move |stream| (source, stream))
+            })
+            .collect();
+        streams.sort_by_key(|(_, stream)| stream.id);
+        let ids: Vec<_> = streams.iter().map(|(_, stream)| stream.id).collect();
+        assert_eq!(ids, [0, 1, 2]);
+        // Streams of a single source keep their order
+        let rates: Vec<_> = streams
+            .iter()
+            .filter(|(source, _)| *source == A)
+            .map(|(_, stream)| stream.spend_rate)
+            .collect();
+        assert_eq!(rates, [SPEND_RATE, 2 * SPEND_RATE]);
+        assert_eq!(StreamPayments::next_stream_id(), 3);
+        assert!(streams.iter().all(|(_, stream)| stream.started_at == 7));
+        assert!(streams
+            .iter()
+            .all(|(_, stream)| stream.asset == StreamAsset::Native));
+        assert!(streams
+            .iter()
+            .all(|(source, stream)| StreamPayments::stream_source(stream.id) == Some(*source)));
+        assert!(streams.iter().all(|(source, stream)| {
+            StreamPayments::incoming_streams(stream.target).contains(&(*source, stream.id))
+        }));
+        assert_eq!(StreamPayments::incoming_streams(B).len(), 2);
+        assert_eq!(StreamPayments::on_chain_storage_version(), 1);
+
+        // Running the migration again is a no-op
+        let before = migrations::stream_stats::<Test>();
+        migrations::Migration::<Test>::on_runtime_upgrade();
+        assert_eq!(StreamPayments::next_stream_id(), 3);
+        assert_eq!(migrations::check_upgrade::<Test>(before), Ok(()));
+    });
+}
+
+#[test]
+fn try_state() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            StreamOptions {
+                max_amount: Some(10 * SPEND_RATE),
+                ..Default::default()
+            }
+        ));
+        assert_eq!(StreamPayments::do_try_state(), Ok(()));
+
+        // Part of the reserve slashed behind the back of the pallet
+        Balances::slash_reserved(&A, 1);
+        assert_eq!(
+            StreamPayments::do_try_state(),
+            Err("Reserved balance does not cover stream deposits")
+        );
+        assert_ok!(Balances::reserve(&A, 1));
+
+        let streams = StreamPayments::streams(A);
+        let mut reflexive = streams.clone().into_inner();
+        reflexive[0].target = A;
+        <pallet::Streams<Test>>::insert(A, pallet::StreamVec::<Test>::try_from(reflexive).unwrap());
+        assert_eq!(StreamPayments::do_try_state(), Err("Reflexive stream"));
+        <pallet::Streams<Test>>::insert(A, streams);
+
+        <pallet::Streams<Test>>::insert(B, pallet::StreamVec::<Test>::default());
+        assert_eq!(
+            StreamPayments::do_try_state(),
+            Err("Empty stream vector stored")
+        );
+        <pallet::Streams<Test>>::remove(B);
+    });
+}
+
+#[test]
+fn migrate_excess_streams() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(7);
+        // More streams than fit in the new layout
+        let old_streams: Vec<_> = (0..=MAX_STREAMS)
+            .map(|i| (B, (i as u64 + 1) * SPEND_RATE))
+            .collect();
+        unhashed::put(&<pallet::Streams<Test>>::hashed_key_for(A), &old_streams);
+        assert_ok!(Balances::reserve(
+            &A,
+            (MAX_STREAMS as u64 + 1) * STREAM_DEPOSIT
+        ));
+        let before = migrations::stream_stats::<Test>();
+        assert_eq!(before.streams, MAX_STREAMS + 1);
+        assert_eq!(before.excess, 1);
+
+        migrations::Migration::<Test>::on_runtime_upgrade();
+        assert_eq!(migrations::check_upgrade::<Test>(before), Ok(()));
+
+        // The oldest streams are kept and indexed, the last one is closed
+        let streams = StreamPayments::streams(A);
+        let ids: Vec<_> = streams.iter().map(|stream| stream.id).collect();
+        assert_eq!(ids, (0..MAX_STREAMS as u64).collect::<Vec<_>>());
+        assert_eq!(
+            streams.last().unwrap().spend_rate,
+            MAX_STREAMS as u64 * SPEND_RATE
+        );
+        assert_eq!(StreamPayments::next_stream_id(), MAX_STREAMS as u64);
+        assert_eq!(
+            <pallet::StreamSources<Test>>::iter_keys().count(),
+            MAX_STREAMS as usize
+        );
+        assert_eq!(
+            StreamPayments::incoming_streams(B).into_inner(),
+            (0..MAX_STREAMS as u64)
+                .map(|id| (A, id))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Balances::reserved_balance(A),
+            MAX_STREAMS as u64 * STREAM_DEPOSIT
+        );
+        assert_eq!(
+            Balances::free_balance(A),
+            INIT_BALANCE - MAX_STREAMS as u64 * STREAM_DEPOSIT
+        );
+    });
+}
+
+#[test]
+fn migration_checks() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(7);
+        // More streams targeting a single account than can be indexed
+        for source in 10..=(10 + MAX_INCOMING_STREAMS as u64) {
+            unhashed::put(
+                &<pallet::Streams<Test>>::hashed_key_for(source),
+                &vec![(B, SPEND_RATE)],
+            );
+            Balances::make_free_balance_be(&source, INIT_BALANCE);
+            assert_ok!(Balances::reserve(&source, STREAM_DEPOSIT));
+        }
+        let before = migrations::stream_stats::<Test>();
+
+        migrations::Migration::<Test>::on_runtime_upgrade();
+        assert_eq!(
+            StreamPayments::incoming_streams(B).len(),
+            MAX_INCOMING_STREAMS as usize
+        );
+        assert_eq!(
+            migrations::check_upgrade::<Test>(before),
+            Err("Stream target not indexed")
+        );
+    });
+
+    new_test_ext().execute_with(|| {
+        assert_ok!(StreamPayments::open_stream(
+            Origin::signed(A),
+            B,
+            StreamAsset::Native,
+            SPEND_RATE,
+            Default::default()
+        ));
+        let before = migrations::stream_stats::<Test>();
+
+        // The migration must bump the storage version
+        assert_eq!(
+            migrations::check_upgrade::<Test>(before),
+            Err("Storage version not updated")
+        );
+
+        // Streams opened in the current layout need no migration
+        StorageVersion::new(1).put::<StreamPayments>();
+        let before = migrations::stream_stats::<Test>();
+        migrations::Migration::<Test>::on_runtime_upgrade();
+        assert_eq!(migrations::check_upgrade::<Test>(before), Ok(()));
+
+        let before = migrations::stream_stats::<Test>();
+        <pallet::StreamSources<Test>>::remove(0);
+        assert_eq!(
+            migrations::check_upgrade::<Test>(before),
+            Err("Stream source not indexed")
+        );
+    });
+}
@@ -1,12 +1,12 @@
-
-//! Autogenerated weights for `pallet_stream_payments`
+//! Weights for `pallet_stream_payments`
 //!
-//! THIS FILE WAS AUTO-GENERATED USING THE SUBSTRATE BENCHMARK CLI VERSION 4.0.0-dev
-//! DATE: 2022-03-11, STEPS: `20`, REPEAT: 100, LOW RANGE: `[]`, HIGH RANGE: `[]`
-//! EXECUTION: Some(Wasm), WASM-EXECUTION: Compiled, CHAIN: Some("dev"), DB CACHE: 1024
-//! Benchmarks computed with `MaxStreams = 128`
+//! THESE WEIGHTS ARE HAND-WRITTEN ESTIMATES, NOT THE OUTPUT OF THE SUBSTRATE BENCHMARK CLI.
+//! They extend the last benchmark run (CLI VERSION 4.0.0-dev, DATE: 2022-03-11, CHAIN: "dev",
+//! `MaxStreams = 128`) of `open_stream`, `close_stream` and the per-block settlement to the
+//! storage accesses and transfers of the calls added since. Regenerate them with the command
+//! below before relying on them.
 
-// Executed Command:
+// Command to regenerate:
 // ./target/release/node-template
 // benchmark
 // --chain=dev
@@ -28,43 +28,266 @@ use sp_std::marker::PhantomData;
 /// Weight functions for `pallet_stream_payments`.
 pub trait WeightInfo {
 	fn open_stream() -> Weight;
-	fn close_stream(_i: u32, ) -> Weight;
-	fn on_initialize_transfer(i: u32, ) -> Weight;
+	fn open_escrowed_stream() -> Weight;
+	fn open_split_stream(r: u32, ) -> Weight;
+	fn close_stream(_i: u32, r: u32, ) -> Weight;
+	fn accept_stream() -> Weight;
+	fn reject_stream(r: u32, ) -> Weight;
+	fn set_incoming_policy() -> Weight;
+	fn set_allowed_source() -> Weight;
+	fn withdraw(r: u32, ) -> Weight;
+	fn claim(r: u32, ) -> Weight;
+	fn transfer_stream_target() -> Weight;
+	fn transfer_stream_source() -> Weight;
+	fn accept_stream_source(r: u32, ) -> Weight;
+	fn update_stream() -> Weight;
+	fn top_up_stream() -> Weight;
+	fn reduce_funding() -> Weight;
+	fn pause_stream() -> Weight;
+	fn resume_stream() -> Weight;
+	fn settle_stream(r: u32, ) -> Weight;
+	fn settle_share_stream() -> Weight;
+	fn on_initialize_transfer(i: u32, r: u32, ) -> Weight;
 	fn on_initialize_stream_exhausted(i: u32, ) -> Weight;
+	fn on_initialize_share_payment(i: u32, ) -> Weight;
 }
 
 /// Weight functions for `pallet_stream_payments`.
 pub struct SubstrateWeight<T>(PhantomData<T>);
 impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
+	// Storage: StreamPayments IncomingPolicies (r:1 w:0)
+	// Storage: StreamPayments AllowedSources (r:1 w:0)
+	// Storage: StreamPayments NextStreamId (r:1 w:1)
 	// Storage: StreamPayments Streams (r:1 w:1)
+	// Storage: StreamPayments IncomingStreams (r:1 w:1)
+	// Storage: System Account (r:1 w:1)
+	// Storage: StreamPayments StreamSources (r:0 w:1)
 	fn open_stream() -> Weight {
-		(29_260_000 as Weight)
+		(36_281_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(6 as Weight))
+			.saturating_add(T::DbWeight::get().writes(5 as Weight))
+	}
+	// Storage: StreamPayments IncomingPolicies (r:1 w:0)
+	// Storage: StreamPayments AllowedSources (r:1 w:0)
+	// Storage: StreamPayments NextStreamId (r:1 w:1)
+	// Storage: StreamPayments Streams (r:1 w:1)
+	// Storage: StreamPayments IncomingStreams (r:1 w:1)
+	// Storage: System Account (r:2 w:2)
+	// Storage: StreamPayments StreamSources (r:0 w:1)
+	fn open_escrowed_stream() -> Weight {
+		(71_494_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(7 as Weight))
+			.saturating_add(T::DbWeight::get().writes(6 as Weight))
+	}
+	// Storage: StreamPayments IncomingPolicies (r:1 w:0)
+	// Storage: StreamPayments AllowedSources (r:1 w:0)
+	// Storage: StreamPayments NextStreamId (r:1 w:1)
+	// Storage: StreamPayments Streams (r:1 w:1)
+	// Storage: StreamPayments IncomingStreams (r:1 w:1)
+	// Storage: System Account (r:1 w:1)
+	// Storage: StreamPayments StreamSources (r:0 w:1)
+	// Storage: StreamPayments StreamShares (r:0 w:1)
+	fn open_split_stream(r: u32, ) -> Weight {
+		(37_694_000 as Weight)
+			.saturating_add((7_318_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(T::DbWeight::get().reads(6 as Weight))
+			.saturating_add(T::DbWeight::get().reads((3 as Weight).saturating_mul(r as Weight)))
+			.saturating_add(T::DbWeight::get().writes(6 as Weight))
+			.saturating_add(T::DbWeight::get().writes((1 as Weight).saturating_mul(r as Weight)))
+	}
+	// Storage: StreamPayments Streams (r:1 w:1)
+	// Storage: System Account (r:1 w:1)
+	// Storage: StreamPayments IncomingStreams (r:1 w:1)
+	// Storage: StreamPayments StreamSources (r:0 w:1)
+	// Storage: StreamPayments StreamShares (r:1 w:1)
+	fn close_stream(_i: u32, r: u32, ) -> Weight {
+		(80_412_000 as Weight)
+			.saturating_add((21_083_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+			.saturating_add(T::DbWeight::get().writes(5 as Weight))
+			.saturating_add(T::DbWeight::get().reads((2 as Weight).saturating_mul(r as Weight)))
+			.saturating_add(T::DbWeight::get().writes((2 as Weight).saturating_mul(r as Weight)))
+	}
+	// Storage: StreamPayments StreamSources (r:1 w:0)
+	// Storage: StreamPayments Streams (r:1 w:1)
+	fn accept_stream() -> Weight {
+		(27_652_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(2 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: StreamPayments StreamSources (r:1 w:1)
+	// Storage: StreamPayments Streams (r:1 w:1)
+	// Storage: StreamPayments StreamShares (r:1 w:1)
+	// Storage: StreamPayments IncomingStreams (r:1 w:1)
+	// Storage: System Account (r:1 w:1)
+	fn reject_stream(r: u32, ) -> Weight {
+		(50_377_000 as Weight)
+			.saturating_add((2_631_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(T::DbWeight::get().reads(5 as Weight))
+			.saturating_add(T::DbWeight::get().writes(5 as Weight))
+			.saturating_add(T::DbWeight::get().reads((1 as Weight).saturating_mul(r as Weight)))
+			.saturating_add(T::DbWeight::get().writes((1 as Weight).saturating_mul(r as Weight)))
+	}
+	// Storage: StreamPayments IncomingPolicies (r:0 w:1)
+	fn set_incoming_policy() -> Weight {
+		(12_305_000 as Weight)
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: StreamPayments AllowedSources (r:0 w:1)
+	fn set_allowed_source() -> Weight {
+		(12_871_000 as Weight)
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: StreamPayments StreamSources (r:1 w:0)
+	// Storage: StreamPayments Streams (r:1 w:1)
+	// Storage: StreamPayments StreamShares (r:1 w:0)
+	// Storage: System Account (r:1 w:1)
+	fn withdraw(r: u32, ) -> Weight {
+		(53_811_000 as Weight)
+			.saturating_add((18_452_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+			.saturating_add(T::DbWeight::get().reads((1 as Weight).saturating_mul(r as Weight)))
+			.saturating_add(T::DbWeight::get().writes((1 as Weight).saturating_mul(r as Weight)))
+	}
+	// Storage: StreamPayments StreamSources (r:1 w:0)
+	// Storage: StreamPayments Streams (r:1 w:1)
+	// Storage: StreamPayments StreamShares (r:1 w:0)
+	// Storage: System Account (r:2 w:2)
+	fn claim(r: u32, ) -> Weight {
+		(58_402_000 as Weight)
+			.saturating_add((18_452_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(T::DbWeight::get().reads(5 as Weight))
+			.saturating_add(T::DbWeight::get().writes(3 as Weight))
+			.saturating_add(T::DbWeight::get().reads((1 as Weight).saturating_mul(r as Weight)))
+			.saturating_add(T::DbWeight::get().writes((1 as Weight).saturating_mul(r as Weight)))
+	}
+	// Storage: StreamPayments IncomingPolicies (r:1 w:0)
+	// Storage: StreamPayments AllowedSources (r:1 w:0)
+	// Storage: StreamPayments StreamSources (r:1 w:0)
+	// Storage: StreamPayments Streams (r:1 w:1)
+	// Storage: System Account (r:1 w:1)
+	fn transfer_stream_target() -> Weight {
+		(58_973_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(5 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	// Storage: StreamPayments Streams (r:1 w:0)
+	// Storage: StreamPayments SourceTransfers (r:0 w:1)
+	fn transfer_stream_source() -> Weight {
+		(20_416_000 as Weight)
 			.saturating_add(T::DbWeight::get().reads(1 as Weight))
 			.saturating_add(T::DbWeight::get().writes(1 as Weight))
 	}
+	// Storage: StreamPayments StreamSources (r:1 w:1)
+	// Storage: StreamPayments SourceTransfers (r:1 w:1)
+	// Storage: StreamPayments Streams (r:2 w:2)
+	// Storage: StreamPayments StreamShares (r:1 w:0)
+	// Storage: StreamPayments IncomingPolicies (r:1 w:0)
+	// Storage: System Account (r:3 w:3)
+	fn accept_stream_source(r: u32, ) -> Weight {
+		(98_964_000 as Weight)
+			.saturating_add((23_838_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(T::DbWeight::get().reads(9 as Weight))
+			.saturating_add(T::DbWeight::get().writes(7 as Weight))
+			.saturating_add(T::DbWeight::get().reads((3 as Weight).saturating_mul(r as Weight)))
+			.saturating_add(T::DbWeight::get().writes((2 as Weight).saturating_mul(r as Weight)))
+	}
 	// Storage: StreamPayments Streams (r:1 w:1)
-	fn close_stream(_i: u32, ) -> Weight {
-		(47_173_000 as Weight)
+	// Storage: System Account (r:1 w:1)
+	fn update_stream() -> Weight {
+		(51_274_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(2 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	// Storage: StreamPayments Streams (r:1 w:1)
+	// Storage: System Account (r:2 w:2)
+	fn top_up_stream() -> Weight {
+		(47_329_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(3 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	// Storage: StreamPayments Streams (r:1 w:1)
+	// Storage: System Account (r:3 w:3)
+	fn reduce_funding() -> Weight {
+		(82_614_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+			.saturating_add(T::DbWeight::get().writes(4 as Weight))
+	}
+	// Storage: StreamPayments Streams (r:1 w:1)
+	// Storage: System Account (r:1 w:1)
+	fn pause_stream() -> Weight {
+		(49_537_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(2 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	// Storage: StreamPayments Streams (r:1 w:1)
+	fn resume_stream() -> Weight {
+		(21_046_000 as Weight)
 			.saturating_add(T::DbWeight::get().reads(1 as Weight))
 			.saturating_add(T::DbWeight::get().writes(1 as Weight))
 	}
-	// Storage: StreamPayments Streams (r:1 w:0)
-	// Storage: System Account (r:6 w:6)
-	fn on_initialize_transfer(i: u32, ) -> Weight {
+	// Storage: StreamPayments StreamSources (r:1 w:0)
+	// Storage: StreamPayments Streams (r:1 w:1)
+	// Storage: StreamPayments StreamShares (r:1 w:0)
+	// Storage: System Account (r:1 w:1)
+	fn settle_stream(r: u32, ) -> Weight {
+		(53_811_000 as Weight)
+			.saturating_add((18_452_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+			.saturating_add(T::DbWeight::get().reads((1 as Weight).saturating_mul(r as Weight)))
+			.saturating_add(T::DbWeight::get().writes((1 as Weight).saturating_mul(r as Weight)))
+	}
+	// Storage: StreamPayments StreamSources (r:1 w:1)
+	// Storage: StreamPayments Streams (r:1 w:1)
+	// Storage: StreamPayments StreamShares (r:1 w:1)
+	// Storage: StreamPayments IncomingStreams (r:1 w:1)
+	// Storage: StreamPayments SourceTransfers (r:0 w:1)
+	// Storage: System Account (r:1 w:1)
+	fn settle_share_stream() -> Weight {
+		(88_131_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(5 as Weight))
+			.saturating_add(T::DbWeight::get().writes(6 as Weight))
+	}
+	// Storage: StreamPayments SettlementCursor (r:1 w:1)
+	// Storage: StreamPayments StreamSources (r:1 w:0)
+	// Storage: StreamPayments Streams (r:1 w:1)
+	// Storage: StreamPayments StreamShares (r:1 w:0)
+	// Storage: System Account (r:1 w:1)
+	fn on_initialize_transfer(i: u32, r: u32, ) -> Weight {
 		(23_858_000 as Weight)
-			// Standard Error: 13_000
-			.saturating_add((29_953_000 as Weight).saturating_mul(i as Weight))
-			.saturating_add(T::DbWeight::get().reads(2 as Weight))
-			.saturating_add(T::DbWeight::get().reads((1 as Weight).saturating_mul(i as Weight)))
+			.saturating_add((35_213_000 as Weight).saturating_mul(i as Weight))
+			.saturating_add((18_452_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().reads((4 as Weight).saturating_mul(i as Weight)))
 			.saturating_add(T::DbWeight::get().writes(1 as Weight))
-			.saturating_add(T::DbWeight::get().writes((1 as Weight).saturating_mul(i as Weight)))
+			.saturating_add(T::DbWeight::get().writes((2 as Weight).saturating_mul(i as Weight)))
+			.saturating_add(T::DbWeight::get().reads((1 as Weight).saturating_mul(r as Weight)))
+			.saturating_add(T::DbWeight::get().writes((1 as Weight).saturating_mul(r as Weight)))
 	}
-	// Storage: StreamPayments Streams (r:1 w:0)
+	// Storage: StreamPayments SettlementCursor (r:1 w:1)
+	// Storage: StreamPayments StreamSources (r:1 w:1)
+	// Storage: StreamPayments Streams (r:1 w:1)
 	fn on_initialize_stream_exhausted(i: u32, ) -> Weight {
 		(4_391_000 as Weight)
-			// Standard Error: 11_000
-			.saturating_add((14_702_000 as Weight).saturating_mul(i as Weight))
-			.saturating_add(T::DbWeight::get().reads(2 as Weight))
+			.saturating_add((19_964_000 as Weight).saturating_mul(i as Weight))
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().reads((2 as Weight).saturating_mul(i as Weight)))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes((2 as Weight).saturating_mul(i as Weight)))
+	}
+	// Storage: StreamPayments SettlementCursor (r:1 w:1)
+	// Storage: StreamPayments StreamSources (r:1 w:0)
+	// Storage: StreamPayments StreamShares (r:1 w:0)
+	// Storage: StreamPayments Streams (r:1 w:1)
+	// Storage: System Account (r:1 w:1)
+	fn on_initialize_share_payment(i: u32, ) -> Weight {
+		(23_858_000 as Weight)
+			.saturating_add((41_276_000 as Weight).saturating_mul(i as Weight))
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().reads((4 as Weight).saturating_mul(i as Weight)))
 			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes((2 as Weight).saturating_mul(i as Weight)))
 	}
 }